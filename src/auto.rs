@@ -1,22 +1,58 @@
 use std::time::Duration;
 use std::thread::sleep;
-use std::{io, iter};
+use std::process::Command;
+use std::{cmp, io, iter};
 use nvapi::{
-    Gpu, ClockDomain,
+    Status, Gpu, ClockDomain,
     CoolerPolicy, CoolerLevel,
     Microvolts, Kilohertz, KilohertzDelta, Percentage, Range,
 };
 use nvapi::nvapi::{
     ClockFrequencyType,
 };
+use types::AutoStrategy;
+use policy::Policy;
 use Error;
 
+/// How the exit code of a test binary is interpreted.
+///
+/// Codes in none of the lists fail the test, unless `fail` is given, in which case they are an error.
+#[derive(Debug, Clone)]
+pub struct ExitCodes {
+    pub pass: Vec<i32>,
+    pub fail: Option<Vec<i32>>,
+    /// Run the test again
+    pub retry: Vec<i32>,
+    /// Stop the sweep
+    pub abort: Vec<i32>,
+}
+
+const TEST_RETRIES: usize = 3;
+
 pub struct AutoDetectOptions {
     pub fan_override: bool,
     pub step: KilohertzDelta,
     pub test: Option<String>,
+    pub test_args: Vec<String>,
+    /// Extra environment variables for the testing binary, on top of the inherited ones
+    pub test_env: Vec<(String, String)>,
+    pub exit_codes: ExitCodes,
     pub voltage_wait_delay: Duration,
     pub max_frequency: Kilohertz,
+    pub settle_tolerance: KilohertzDelta,
+    pub settle_max: Duration,
+    pub strategy: AutoStrategy,
+    pub detect_reset: bool,
+    /// Caps every write the sweep makes, values over a cap are clamped
+    pub policy: Policy,
+}
+
+/// Outcome of testing a single curve point.
+pub struct PointResult {
+    pub delta: KilohertzDelta,
+    pub frequency: Kilohertz,
+    /// Lowest frequency that failed, if any did
+    pub failed: Option<Kilohertz>,
 }
 
 pub struct AutoDetect<'a> {
@@ -66,6 +102,38 @@ impl<'a> AutoDetect<'a> {
         Ok(false)
     }
 
+    /// Polls the clock until two successive reads are within `settle_tolerance` of each other.
+    pub fn wait_for_settle(&self) -> Result<bool, Error> {
+        let interval = Duration::from_millis(100);
+        let mut waited = Duration::from_secs(0);
+        let mut previous = self.current_clock()?;
+
+        while waited < self.options.settle_max {
+            sleep(interval);
+            waited += interval;
+
+            let clock = self.current_clock()?;
+            if (clock - previous).0.abs() <= self.options.settle_tolerance.0 {
+                return Ok(true)
+            }
+            previous = clock;
+        }
+
+        warn!("Clock still changing after {:?}, testing anyway", self.options.settle_max);
+        Ok(false)
+    }
+
+    /// Whether the driver appears to have reset the GPU since `delta` was applied to point `index`,
+    /// which clears all offsets. A reset that also invalidated the GPU handle is an error.
+    pub fn reset_detected(&self, index: usize, delta: KilohertzDelta) -> Result<bool, Error> {
+        match self.gpu.settings() {
+            Ok(settings) => Ok(settings.vfp.and_then(|vfp| vfp.graphics.get(&index).cloned())
+                .map(|current| current != delta).unwrap_or(false)),
+            Err(Status::HandleInvalidated) => Err(Error::Str("the GPU was reset by the driver and can no longer be accessed")),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     pub fn test_prepare(&self) -> Result<(), Error> {
         if !self.options.fan_override {
             self.gpu.set_cooler_levels(vec![CoolerLevel {
@@ -75,8 +143,11 @@ impl<'a> AutoDetect<'a> {
         }
 
         let info = self.gpu.info()?;
+        let limits = info.power_limits.iter()
+            .map(|info| self.options.policy.power_limit(info.range.max))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        self.gpu.set_power_limits(info.power_limits.iter().map(|info| info.range.max))?;
+        self.gpu.set_power_limits(limits.into_iter())?;
         //self.gpu.reset_vfp()?;
 
         Ok(())
@@ -91,9 +162,14 @@ impl<'a> AutoDetect<'a> {
     }
 
     pub fn set_voltage(&mut self, voltage: Microvolts, frequency: Kilohertz) -> Result<bool, Error> {
+        if let Err(e) = self.options.policy.lock_voltage(voltage, None, None) {
+            warn!("{}", e);
+            return Ok(false)
+        }
+
         self.gpu.set_vfp_lock(voltage)?;
         let reached_voltage = if !self.wait_for_voltage(voltage, frequency, self.options.voltage_wait_delay)? {
-            let full = Percentage(100);
+            let full = self.options.policy.voltage_boost(Percentage(100))?;
             if self.voltage_boost < full {
                 warn!("Boosting core voltage");
                 self.gpu.set_voltage_boost(full)?;
@@ -120,7 +196,35 @@ impl<'a> AutoDetect<'a> {
 
     pub fn run_test_operation(&mut self, voltage: Microvolts, frequency: Kilohertz) -> Result<bool, Error> {
         if let Some(ref test) = self.options.test {
-            unimplemented!()
+            let codes = &self.options.exit_codes;
+            for _ in 0..TEST_RETRIES {
+                let status = Command::new(test).args(&self.options.test_args)
+                    .envs(self.options.test_env.iter().map(|(k, v)| (k, v)))
+                    .status()?;
+                info!("{} @ {}: test exited with {}", frequency, voltage, status);
+
+                let code = match status.code() {
+                    Some(code) => code,
+                    // killed by a signal
+                    None => return Ok(false),
+                };
+
+                if codes.pass.contains(&code) {
+                    return Ok(true)
+                } else if codes.retry.contains(&code) {
+                    warn!("Test asked to be retried with exit code {}", code);
+                } else if codes.abort.contains(&code) {
+                    return Err(Error::from(format!("test aborted the sweep with exit code {}", code)))
+                } else {
+                    match codes.fail {
+                        Some(ref fail) if !fail.contains(&code) =>
+                            return Err(Error::from(format!("test exited with unexpected code {}", code))),
+                        _ => return Ok(false),
+                    }
+                }
+            }
+
+            Err(Error::from(format!("test still asked to be retried after {} attempts", TEST_RETRIES)))
         } else {
             //unimplemented!()
             loop {
@@ -136,7 +240,7 @@ impl<'a> AutoDetect<'a> {
         }
     }
 
-    pub fn test_point(&mut self, index: usize, voltage: Microvolts, frequency: Kilohertz, delta: KilohertzDelta) -> Result<Option<(KilohertzDelta, Kilohertz)>, Error> {
+    pub fn test_point(&mut self, index: usize, voltage: Microvolts, frequency: Kilohertz, delta: KilohertzDelta) -> Result<Option<PointResult>, Error> {
         let base_frequency = frequency - delta;
 
         info!("Testing point {}: current frequency {} (base {})", voltage, frequency, base_frequency);
@@ -154,9 +258,15 @@ impl<'a> AutoDetect<'a> {
             },
             min: delta,
         };
+        // never try an offset over the policy cap
+        valid.max = cmp::max(valid.min, self.options.policy.vfp_delta(valid.max)?);
+        let mut failed = None;
 
         loop {
-            let delta = (valid.max - valid.min) * 3 / 4;
+            let delta = match self.options.strategy {
+                AutoStrategy::Down => (valid.max - valid.min) * 3 / 4,
+                AutoStrategy::Binary => (valid.max - valid.min) / 2,
+            };
             let delta = delta / self.options.step.0 * self.options.step.0;
             let delta = valid.min + delta;
             if delta == valid.min {
@@ -167,12 +277,20 @@ impl<'a> AutoDetect<'a> {
             let frequency = base_frequency + delta;
             info!("Testing {}: {}", voltage, frequency);
             self.gpu.set_vfp(iter::once((index, delta)), iter::empty())?;
-            let result = self.run_test_operation(voltage, frequency)?;
+            self.wait_for_settle()?;
+            let mut result = self.run_test_operation(voltage, frequency)?;
+
+            if result && self.options.detect_reset && self.reset_detected(index, delta)? {
+                warn!("GPU reset detected while testing {}, counting it as a failure", frequency);
+                self.gpu.set_vfp_lock(voltage)?;
+                result = false;
+            }
 
             if result {
                 valid.min = delta;
             } else {
                 valid.max = delta - self.options.step;
+                failed = Some(failed.map_or(frequency, |f| cmp::min(f, frequency)));
             }
 
             println!("range now  {:?}", valid);
@@ -183,6 +301,10 @@ impl<'a> AutoDetect<'a> {
 
         let frequency = base_frequency + valid.min;
         self.previous_clock = Some(frequency);
-        Ok(Some((valid.min, frequency)))
+        Ok(Some(PointResult {
+            delta: valid.min,
+            frequency,
+            failed,
+        }))
     }
 }
@@ -1,31 +1,57 @@
-use nvapi::{PState, CoolerPolicy, ClockDomain};
-use types::{ResetSettings, OutputFormat};
+use nvapi::{PState, CoolerPolicy, ClockDomain, ClockLockMode};
+use types::{ResetSettings, SettingField, OutputFormat, ClockUnit, VfpFormat, SchemaKind, AutoStrategy};
+use clap::Arg;
 use error::Error;
 
 pub trait ConvertEnum: Sized {
     fn from_str(s: &str) -> Result<Self, Error>;
     fn to_str(&self) -> &'static str;
-    fn possible_values() -> &'static [&'static str];
+    fn description(&self) -> &'static str;
     fn possible_values_typed() -> &'static [Self];
+    /// The name of every variant along with any aliases
+    fn accepted_values() -> &'static [&'static str];
+}
+
+pub trait EnumArg {
+    /// Restricts the argument to the names `T` accepts, in any case
+    fn enum_values<T: ConvertEnum>(self) -> Self;
+    /// `help`, followed by every value of `T` and its description under `--help`
+    fn enum_help<T: ConvertEnum + 'static>(self, help: &'static str) -> Self;
+}
+
+impl<'a, 'b> EnumArg for Arg<'a, 'b> {
+    fn enum_values<T: ConvertEnum>(self) -> Self {
+        self.possible_values(T::accepted_values()).case_insensitive(true)
+    }
+
+    fn enum_help<T: ConvertEnum + 'static>(self, help: &'static str) -> Self {
+        let values = T::possible_values_typed().iter()
+            .map(|v| format!("    {}: {}", v.to_str(), v.description()))
+            .collect::<Vec<_>>();
+
+        // clap borrows help text for as long as the App lives, and the App is built once per run
+        let long_help: &'static str = Box::leak(format!("{}\n\n{}", help, values.join("\n")).into_boxed_str());
+        self.help(help).long_help(long_help)
+    }
 }
 
 macro_rules! enum_from_str {
     (
         $conv:ident => {
         $(
-            $item:ident = $str:expr,
+            $item:ident = $str:tt $(| $alias:tt)* => $desc:expr,
         )*
             _ => $err:expr,
         }
     ) => {
         impl ConvertEnum for $conv {
             fn from_str(s: &str) -> Result<Self, Error> {
-                match s {
-                $(
-                    $str => Ok($conv::$item),
-                )*
-                    _ => Err(($err).into()),
+            $(
+                if s.eq_ignore_ascii_case($str) $(|| s.eq_ignore_ascii_case($alias))* {
+                    return Ok($conv::$item)
                 }
+            )*
+                Err(($err).into())
             }
 
             #[allow(unreachable_patterns)]
@@ -38,10 +64,14 @@ macro_rules! enum_from_str {
                 }
             }
 
-            fn possible_values() -> &'static [&'static str] {
-                &[$(
-                    $str,
-                )*]
+            #[allow(unreachable_patterns)]
+            fn description(&self) -> &'static str {
+                match *self {
+                $(
+                    $conv::$item => $desc,
+                )*
+                    _ => "unknown",
+                }
             }
 
             fn possible_values_typed() -> &'static [Self] {
@@ -49,74 +79,167 @@ macro_rules! enum_from_str {
                     $conv::$item,
                 )*]
             }
+
+            fn accepted_values() -> &'static [&'static str] {
+                &[$(
+                    $str, $($alias,)*
+                )*]
+            }
         }
     };
 }
 
 enum_from_str! {
     OutputFormat => {
-        Human = "human",
-        Json = "json",
+        Human = "human" | "text" => "Human-readable text and tables",
+        Json = "json" => "Machine-readable JSON",
         _ => "unknown output format",
     }
 }
 
+enum_from_str! {
+    ClockUnit => {
+        Khz = "khz" => "Integer kilohertz, as reported by NVAPI",
+        Mhz = "mhz" => "Fractional megahertz",
+        _ => "unknown clock unit",
+    }
+}
+
+enum_from_str! {
+    VfpFormat => {
+        Csv = "csv" => "Comma or tab separated values",
+        Json = "json" => "JSON array of curve points",
+        _ => "unknown curve format",
+    }
+}
+
+enum_from_str! {
+    AutoStrategy => {
+        Down = "down" => "Try near the top of the untested range first, stepping down on failure",
+        Binary = "binary" => "Bisect between the highest passing and lowest failing clocks",
+        _ => "unknown strategy",
+    }
+}
+
+enum_from_str! {
+    SchemaKind => {
+        Info = "info" => "Output of the info command",
+        Status = "status" => "Output of the status command",
+        Settings = "settings" => "Output of the get command",
+        _ => "unknown schema",
+    }
+}
+
 enum_from_str! {
     ResetSettings => {
-        VoltageBoost = "voltage-boost",
-        SensorLimits = "thermal",
-        PowerLimits = "power",
-        CoolerLevels = "cooler",
-        VfpDeltas = "vfp",
-        VfpLock = "lock",
-        PStateDeltas = "pstate",
-        Overvolt = "overvolt",
+        VoltageBoost = "voltage-boost" => "Core voltage boost back to 0%",
+        SensorLimits = "thermal" => "Thermal limits back to their defaults",
+        PowerLimits = "power" => "Power limits back to their defaults",
+        CoolerLevels = "cooler" => "Cooler policies and levels back to driver control",
+        VfpDeltas = "vfp" => "Clear the per-point voltage-frequency curve offsets",
+        VfpLock = "lock" => "Remove any voltage-frequency curve locks",
+        PStateDeltas = "pstate" => "Clear the pstate clock offsets, which shift the whole curve",
+        Overvolt = "overvolt" => "Remove any overvolt offsets",
         _ => "unknown setting",
     }
 }
 
+enum_from_str! {
+    SettingField => {
+        VoltageBoost = "voltage-boost" => "Core voltage boost",
+        ThermalLimit = "thermal-limit" => "Thermal limits",
+        PowerLimit = "power-limit" => "Power limits",
+        CoolerLevel = "cooler-level" => "Cooler levels",
+        Overvolt = "overvolt" => "Overvolt offsets",
+        VfpLock = "vfp-lock" => "Locked voltage-frequency curve voltage",
+        _ => "unknown setting field",
+    }
+}
+
 enum_from_str! {
     PState => {
-        P0 = "P0",
-        P1 = "P1",
-        P2 = "P2",
-        P3 = "P3",
-        P4 = "P4",
-        P5 = "P5",
-        P6 = "P6",
-        P7 = "P7",
-        P8 = "P8",
-        P9 = "P9",
-        P10 = "P10",
-        P11 = "P11",
-        P12 = "P12",
-        P13 = "P13",
-        P14 = "P14",
-        P15 = "P15",
+        P0 = "P0" => "Maximum 3D performance",
+        P1 = "P1" => "Maximum 3D performance",
+        P2 = "P2" => "Balanced 3D performance-power",
+        P3 = "P3" => "Balanced 3D performance-power",
+        P4 = "P4" => "Undocumented",
+        P5 = "P5" => "Undocumented",
+        P6 = "P6" => "Undocumented",
+        P7 = "P7" => "Undocumented",
+        P8 = "P8" => "Basic HD video playback",
+        P9 = "P9" => "Undocumented",
+        P10 = "P10" => "DVD playback",
+        P11 = "P11" => "Undocumented",
+        P12 = "P12" => "Minimum idle power consumption",
+        P13 = "P13" => "Undocumented",
+        P14 = "P14" => "Undocumented",
+        P15 = "P15" => "Lowest performance",
         _ => "unknown pstate",
     }
 }
 
 enum_from_str! {
     ClockDomain => {
-        Graphics = "graphics",
-        Memory = "memory",
-        Processor = "processor",
-        Video = "video",
+        Graphics = "graphics" | "core" => "GPU core clock",
+        Memory = "memory" | "mem" => "Video memory clock",
+        Processor = "processor" => "Shader processor clock",
+        Video = "video" => "Video engine clock",
         _ => "unknown clock type",
     }
 }
 
+enum_from_str! {
+    ClockLockMode => {
+        Manual = "manual" => "Lock the clock to a single curve point",
+        None = "none" => "Let GPU Boost pick the point",
+        _ => "unknown lock mode",
+    }
+}
+
 enum_from_str! {
     CoolerPolicy => {
-        None = "default",
-        Manual = "manual",
-        Performance = "perf",
-        TemperatureDiscrete = "discrete",
-        TemperatureContinuous = "continuous",
-        Hybrid = "hybrid",
-        Silent = "silent",
-        Unknown32 = "32",
+        None = "default" | "auto" => "Driver default policy",
+        Manual = "manual" => "Fixed level set by the user",
+        Performance = "perf" => "Level follows the GPU performance state",
+        TemperatureDiscrete = "discrete" => "Level follows discrete thermal steps",
+        TemperatureContinuous = "continuous" => "Level follows temperature continuously",
+        Hybrid = "hybrid" => "Level follows both the performance state and temperature",
+        Silent = "silent" => "Undocumented, fans stop while idle on some cards",
+        Unknown32 = "32" => "Undocumented",
         _ => "unknown cooler policy",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nvapi::{PState, CoolerPolicy, ClockDomain};
+    use types::{OutputFormat, ResetSettings};
+    use super::ConvertEnum;
+
+    #[test]
+    fn case_insensitive() {
+        assert_eq!(PState::from_str("p0").unwrap(), PState::P0);
+        assert_eq!(PState::from_str("P0").unwrap(), PState::P0);
+        assert_eq!(ClockDomain::from_str("GRAPHICS").unwrap(), ClockDomain::Graphics);
+        assert_eq!(ResetSettings::from_str("Power").unwrap(), ResetSettings::PowerLimits);
+        assert!(PState::from_str("P16").is_err());
+    }
+
+    #[test]
+    fn aliases() {
+        assert_eq!(CoolerPolicy::from_str("auto").unwrap(), CoolerPolicy::None);
+        assert_eq!(CoolerPolicy::from_str("AUTO").unwrap(), CoolerPolicy::None);
+        assert_eq!(ClockDomain::from_str("core").unwrap(), ClockDomain::Graphics);
+        match OutputFormat::from_str("text").unwrap() {
+            OutputFormat::Human => (),
+            f => panic!("text parsed as {:?}", f),
+        }
+    }
+
+    #[test]
+    fn canonical() {
+        assert_eq!(CoolerPolicy::None.to_str(), "default");
+        assert!(!CoolerPolicy::possible_values_typed().iter().any(|p| p.to_str() == "auto"));
+        assert!(CoolerPolicy::accepted_values().contains(&"auto"));
+    }
+}
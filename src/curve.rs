@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+use std::cmp;
+use nvapi::{VfPoint, KilohertzDelta, Range};
+
+pub fn clamp_delta(delta: KilohertzDelta, range: &Range<KilohertzDelta>) -> KilohertzDelta {
+    cmp::max(range.min, cmp::min(range.max, delta))
+}
+
+/// Fills in offsets for the curve points between each pair of anchors, linearly by voltage.
+///
+/// Anchors are kept as-is, interpolated offsets are clamped to `range`. Pairs of anchors
+/// whose resulting frequencies would decrease are left alone.
+pub fn interpolate(curve: &BTreeMap<usize, VfPoint>, anchors: &BTreeMap<usize, KilohertzDelta>, range: &Range<KilohertzDelta>) -> BTreeMap<usize, KilohertzDelta> {
+    let mut deltas = anchors.clone();
+
+    for ((&i0, &d0), (&i1, &d1)) in anchors.iter().zip(anchors.iter().skip(1)) {
+        let (p0, p1) = match (curve.get(&i0), curve.get(&i1)) {
+            (Some(p0), Some(p1)) => (p0, p1),
+            _ => continue,
+        };
+
+        if p0.frequency - p0.delta + d0 > p1.frequency - p1.delta + d1 {
+            warn!("Not interpolating between points {} and {}: frequency decreases", i0, i1);
+            continue
+        }
+
+        let span = p1.voltage.0 as i64 - p0.voltage.0 as i64;
+        for (&i, point) in curve.range(i0 + 1..i1) {
+            let delta = if span == 0 {
+                d0
+            } else {
+                let offset = point.voltage.0 as i64 - p0.voltage.0 as i64;
+                KilohertzDelta(d0.0 + ((d1.0 - d0.0) as i64 * offset / span) as i32)
+            };
+
+            deltas.insert(i, clamp_delta(delta, range));
+        }
+    }
+
+    deltas
+}
+
+/// Lowers any point whose frequency would exceed that of a higher voltage point.
+///
+/// Points missing from `deltas` keep their current offset from `curve`. Returns the
+/// adjusted points along with the offsets they had before.
+pub fn enforce_monotonic(curve: &BTreeMap<usize, VfPoint>, deltas: &mut BTreeMap<usize, KilohertzDelta>) -> Vec<(usize, KilohertzDelta)> {
+    let mut adjusted = Vec::new();
+    let mut ceiling = None;
+
+    for (&i, point) in curve.iter().rev() {
+        let base = point.frequency - point.delta;
+        let delta = deltas.get(&i).cloned().unwrap_or(point.delta);
+        let frequency = base + delta;
+
+        match ceiling {
+            Some(max) if frequency > max => {
+                deltas.insert(i, max - base);
+                adjusted.push((i, delta));
+            },
+            _ => ceiling = Some(frequency),
+        }
+    }
+
+    adjusted.reverse();
+    adjusted
+}
@@ -0,0 +1,108 @@
+use nvapi::{self, Status, Gpu};
+use types::{DoctorCheck, DoctorResult};
+use error::Error;
+
+fn check<S: Into<String>, D: Into<String>>(name: S, result: DoctorResult, detail: D) -> DoctorCheck {
+    DoctorCheck {
+        name: name.into(),
+        result,
+        detail: detail.into(),
+    }
+}
+
+fn describe(status: Status) -> String {
+    Error::from(status).to_string()
+}
+
+fn feature(checks: &mut Vec<DoctorCheck>, index: usize, name: &str, supported: bool) {
+    checks.push(if supported {
+        check(format!("GPU {} {}", index, name), DoctorResult::Pass, "supported")
+    } else {
+        check(format!("GPU {} {}", index, name), DoctorResult::Warn, "not supported")
+    });
+}
+
+/// Writes back the current voltage boost, or failing that the power limits, to see whether writes are allowed.
+fn write_access(gpu: &Gpu) -> Result<&'static str, Status> {
+    match gpu.inner().core_voltage_boost() {
+        Ok(boost) => gpu.set_voltage_boost(boost).map(|_| "voltage boost"),
+        Err(..) => {
+            let settings = gpu.settings()?;
+            gpu.set_power_limits(settings.power_limits.iter().cloned()).map(|_| "power limits")
+        },
+    }
+}
+
+/// Runs through everything nvoclock depends on, stopping early once nothing further can be checked.
+pub fn diagnose(init: nvapi::Result<()>, gpu: Option<::clap::Values>) -> Vec<DoctorCheck> {
+    let mut checks = Vec::new();
+
+    if let Err(e) = init {
+        checks.push(check("NVAPI", DoctorResult::Fail, describe(e)));
+        return checks
+    }
+    checks.push(check("NVAPI", DoctorResult::Pass, "initialized"));
+
+    checks.push(match nvapi::driver_version() {
+        Ok((version, branch)) => check("Driver", DoctorResult::Pass, format!("{}.{:02} ({})", version / 100, version % 100, branch)),
+        Err(e) => check("Driver", DoctorResult::Fail, describe(e)),
+    });
+
+    checks.push(match nvapi::interface_version() {
+        Ok(interface) => check("Interface", DoctorResult::Pass, interface),
+        Err(e) => check("Interface", DoctorResult::Fail, describe(e)),
+    });
+
+    let all = match Gpu::enumerate() {
+        Ok(ref all) if all.is_empty() => {
+            checks.push(check("GPUs", DoctorResult::Fail, "no GPUs found"));
+            return checks
+        },
+        Ok(all) => all,
+        Err(e) => {
+            checks.push(check("GPUs", DoctorResult::Fail, describe(e)));
+            return checks
+        },
+    };
+    checks.push(check("GPUs", DoctorResult::Pass, format!("{} found", all.len())));
+
+    // -g names can only be resolved once the GPUs are enumerated
+    let selected = match ::gpu_indices(&all, gpu) {
+        Ok(selected) => selected,
+        Err(e) => {
+            checks.push(check("GPU selection", DoctorResult::Fail, e.to_string()));
+            return checks
+        },
+    };
+
+    for (i, gpu) in all.iter().enumerate() {
+        if !selected.contains(&i) {
+            continue
+        }
+
+        let name = gpu.inner().full_name().unwrap_or_else(|_| "unknown".into());
+        match gpu.info() {
+            Ok(info) => {
+                checks.push(check(format!("GPU {}", i), DoctorResult::Pass, name));
+                feature(&mut checks, i, "pstate offsets", !info.pstate_limits.is_empty());
+                feature(&mut checks, i, "power limits", !info.power_limits.is_empty());
+                feature(&mut checks, i, "thermal limits", !info.sensor_limits.is_empty());
+                feature(&mut checks, i, "coolers", !info.coolers.is_empty());
+                feature(&mut checks, i, "VFP curve", !info.vfp_limits.is_empty());
+            },
+            Err(e) => checks.push(check(format!("GPU {}", i), DoctorResult::Fail, format!("{}: {}", name, describe(e)))),
+        }
+
+        match gpu.settings() {
+            Ok(settings) => feature(&mut checks, i, "voltage boost", settings.voltage_boost.is_some()),
+            Err(e) => checks.push(check(format!("GPU {} settings", i), DoctorResult::Fail, describe(e))),
+        }
+
+        checks.push(match write_access(gpu) {
+            Ok(setting) => check(format!("GPU {} write access", i), DoctorResult::Pass, format!("rewrote {}", setting)),
+            Err(e) => check(format!("GPU {} write access", i), DoctorResult::Fail, format!("{} (try running as administrator)", describe(e))),
+        });
+    }
+
+    checks
+}
@@ -1,4 +1,5 @@
 use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::num::{ParseIntError, ParseFloatError};
 use types::ResetSettings;
 use nvapi::{Status, error_message};
@@ -11,6 +12,9 @@ quick_error! {
             from()
             display("NVAPI error: {}", error_message(*err).unwrap_or_else(|_| format!("{:?}", err)))
         }
+        NoDriver(err: Status) {
+            display("No NVIDIA driver found; is a supported GPU installed and the driver loaded? ({:?})", err)
+        }
         Io(err: io::Error) {
             from()
             cause(err)
@@ -31,10 +35,17 @@ quick_error! {
             cause(err)
             display("{}", err)
         }
+        Unprivileged {
+            display("this command changes GPU settings, run it as {}", if cfg!(windows) { "administrator" } else { "root" })
+        }
         Str(err: &'static str) {
             from()
             display("{}", err)
         }
+        Message(err: String) {
+            from()
+            display("{}", err)
+        }
         ResetError { setting: ResetSettings, err: Status } {
             from(s: (ResetSettings, Status)) -> {
                 setting: s.0,
@@ -45,6 +56,39 @@ quick_error! {
     }
 }
 
+pub const EXIT_FAILURE: i32 = 1;
+/// Only for a missing driver, other NVAPI failures exit with `EXIT_FAILURE`
+pub const EXIT_NO_DRIVER: i32 = 2;
+pub const EXIT_PRIVILEGE: i32 = 3;
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+
+/// Whether failures should also report the raw NVAPI status (`--debug`)
+pub fn debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+pub fn set_debug(debug: bool) {
+    DEBUG.store(debug, Ordering::Relaxed)
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Unprivileged | Error::Nvapi(Status::InvalidUserPrivilege) => EXIT_PRIVILEGE,
+            Error::NoDriver(..) => EXIT_NO_DRIVER,
+            _ => EXIT_FAILURE,
+        }
+    }
+
+    pub fn status(&self) -> Option<Status> {
+        match *self {
+            Error::Nvapi(err) | Error::NoDriver(err) | Error::ResetError { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
 impl<'a> From<&'a Status> for Error {
     fn from(s: &'a Status) -> Self {
         s.clone().into()
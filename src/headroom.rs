@@ -0,0 +1,34 @@
+use std::cmp::Ordering;
+use nvapi::{GpuInfo, GpuStatus, GpuSettings, ClockDomain};
+use types::{HeadroomConstraint, HeadroomEntry};
+
+/// Compares the current power, temperature, clock and voltage against their limits.
+///
+/// The clock and voltage ceilings come from the top of the curve, falling back to the
+/// boost clock when the curve is unavailable. Entries are sorted with the binding
+/// constraint first.
+pub fn headroom(info: &GpuInfo, status: &GpuStatus, settings: &GpuSettings) -> Vec<HeadroomEntry> {
+    let mut entries = Vec::new();
+
+    if let (Some(power), Some(limit)) = (status.power.first(), settings.power_limits.first()) {
+        entries.push(HeadroomEntry::new(HeadroomConstraint::Power, power.0 as i64, limit.0 as i64));
+    }
+
+    let temperature = status.sensors.iter().map(|&(_, temp)| temp).max();
+    if let (Some(temp), Some(limit)) = (temperature, settings.sensor_limits.first()) {
+        entries.push(HeadroomEntry::new(HeadroomConstraint::Thermal, temp.0 as i64, limit.0 as i64));
+    }
+
+    let top = status.vfp.as_ref().and_then(|vfp| vfp.graphics.values().max_by_key(|p| p.voltage));
+    let clock_limit = top.map(|p| p.frequency).or_else(|| info.boost_clocks.get(&ClockDomain::Graphics).cloned());
+    if let (Some(clock), Some(limit)) = (status.clocks.get(&ClockDomain::Graphics), clock_limit) {
+        entries.push(HeadroomEntry::new(HeadroomConstraint::Clock, clock.0 as i64, limit.0 as i64));
+    }
+
+    if let (Some(voltage), Some(top)) = (status.voltage, top) {
+        entries.push(HeadroomEntry::new(HeadroomConstraint::Voltage, voltage.0 as i64, top.voltage.0 as i64));
+    }
+
+    entries.sort_by(|a, b| b.usage.partial_cmp(&a.usage).unwrap_or(Ordering::Equal));
+    entries
+}
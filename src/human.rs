@@ -1,25 +1,28 @@
 use std::iter;
+use std::collections::VecDeque;
+use std::io::{self, Write};
 use nvapi::{
     GpuInfo, GpuStatus, GpuSettings,
-    Celsius, KilohertzDelta, VfPoint,
+    Celsius, Kilohertz, KilohertzDelta, Percentage, VfPoint,
     ClockDomain, ClockFrequencies, VoltageDomain, Microvolts, PState,
     CoolerDesc, CoolerStatus, CoolerControl, ClockLockMode,
     SensorDesc, SensorLimit, PStateLimit,
     Utilizations, UtilizationDomain,
 };
 use prettytable::{format, Table};
+use types::{DriverDescriptor, SettingDescriptor, VfpImportEntry, VfpVerifyEntry, VfpLockEntry, HeadroomConstraint, HeadroomReport, DoctorCheck, DoctorResult, ResetSummary, ResetOutcome, TempThresholds, TestReport, CoolerPolicies, EffectiveClock, ResetPlan};
 
 const HEADER_LEN: usize = 20;
 
 macro_rules! pline {
-    ($header:expr, $($tt:tt)*) => {
+    ($w:expr, $header:expr, $($tt:tt)*) => {
         {
             let mut header = $header.to_string();
             while header.len() < HEADER_LEN {
                 header.push('.');
             }
-            print!("{}: ", header);
-            println!($($tt)*);
+            write!($w, "{}: ", header)?;
+            writeln!($w, $($tt)*)?;
         }
     };
 }
@@ -40,30 +43,116 @@ fn n_a() -> String {
     "N/A".into()
 }
 
-pub fn print_settings(set: &GpuSettings) {
+/// Always MHz, unlike nvapi's `Display` which switches to kHz below 1 MHz and rounds through `f32`
+fn mhz(khz: i64) -> String {
+    let (sign, khz) = if khz < 0 { ("-", -khz) } else { ("", khz) };
+    match khz % 1000 {
+        0 => format!("{}{} MHz", sign, khz / 1000),
+        rem => format!("{}{}.{} MHz", sign, khz / 1000, format!("{:03}", rem).trim_end_matches('0')),
+    }
+}
+
+fn freq(f: Kilohertz) -> String {
+    mhz(f.0 as i64)
+}
+
+fn offset(d: KilohertzDelta) -> String {
+    mhz(d.0 as i64)
+}
+
+/// Recent samples of the metrics `status --sparklines` draws
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    temperature: VecDeque<i64>,
+    clock: VecDeque<i64>,
+    load: VecDeque<i64>,
+}
+
+const HISTORY_LEN: usize = 30;
+
+impl History {
+    pub fn push(&mut self, status: &GpuStatus) {
+        fn push(samples: &mut VecDeque<i64>, value: Option<i64>) {
+            if let Some(value) = value {
+                if samples.len() == HISTORY_LEN {
+                    samples.pop_front();
+                }
+                samples.push_back(value);
+            }
+        }
+
+        push(&mut self.temperature, status.sensors.iter().map(|&(_, t)| t.0 as i64).max());
+        push(&mut self.clock, status.clocks.get(&ClockDomain::Graphics).map(|c| c.0 as i64 / 1000));
+        push(&mut self.load, status.utilization.get(&UtilizationDomain::Graphics).map(|u| u.0 as i64));
+    }
+}
+
+fn sparkline(samples: &VecDeque<i64>) -> String {
+    const BLOCKS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let min = samples.iter().cloned().min().unwrap_or(0);
+    let max = samples.iter().cloned().max().unwrap_or(0);
+    samples.iter().map(|&v| if max > min {
+        BLOCKS[((v - min) * (BLOCKS.len() as i64 - 1) / (max - min)) as usize]
+    } else {
+        BLOCKS[0]
+    }).collect()
+}
+
+/// Trends of recent samples, or just their range when `plain` (not a terminal)
+pub fn print_history(w: &mut dyn Write, history: &History, plain: bool) -> io::Result<()> {
+    for &(name, samples, unit) in &[
+        ("Temperature Trend", &history.temperature, "C"),
+        ("Clock Trend", &history.clock, " MHz"),
+        ("Load Trend", &history.load, "%"),
+    ] {
+        let (min, max) = match (samples.iter().min(), samples.iter().max()) {
+            (Some(min), Some(max)) => (min, max),
+            _ => continue,
+        };
+
+        if plain {
+            pline!(w, name, "{}{} - {}{}", min, unit, max, unit);
+        } else {
+            pline!(w, name, "{} {}{} - {}{}", sparkline(samples), min, unit, max, unit);
+        }
+    }
+    Ok(())
+}
+
+/// `tdp` is the board power in watts at a 100% power limit, used to also show limits in watts
+pub fn print_settings(w: &mut dyn Write, set: &GpuSettings, tdp: Option<f64>) -> io::Result<()> {
     if let Some(ref boost) = set.voltage_boost {
-        pline!("Voltage Boost", "{}", boost);
+        pline!(w, "Voltage Boost", "{}", boost);
     }
     for limit in &set.sensor_limits {
-        pline!("Thermal Limit", "{}", limit);
+        pline!(w, "Thermal Limit", "{}", limit);
     }
     for limit in &set.power_limits {
-        pline!("Power Limit", "{}", limit);
+        match tdp {
+            Some(tdp) => pline!(w, "Power Limit", "{} ({:.0} W)", limit, tdp * limit.0 as f64 / 100.0),
+            None => pline!(w, "Power Limit", "{}", limit),
+        }
     }
     for &(ref desc, ref cooler) in &set.coolers {
-        pline!(format!("Cooler {}", desc.kind), "{}", cooler.level);
+        pline!(w, format!("Cooler {}", desc.kind), "{}", cooler.level);
     }
     for (pstate, clock, delta) in set.pstate_deltas.iter().flat_map(|(ps, d)| d.iter().map(move |(clock, d)| (ps, clock, d))) {
-        pline!(format!("{} @ {} Offset", clock, pstate), "{}", delta);
+        pline!(w, format!("{} @ {} Offset", clock, pstate), "{}", offset(*delta));
+    }
+    if set.overvolt.is_empty() {
+        pline!(w, "Overvolt", "{}", n_a());
     }
     for ov in &set.overvolt {
-        pline!("Overvolt", "{}", ov);
+        pline!(w, "Overvolt", "{}", ov);
     }
     for (_, lock) in &set.vfp_locks {
         if lock.mode == ClockLockMode::Manual {
-            pline!("VFP Lock", "{}", lock.voltage);
+            pline!(w, "VFP Lock", "{}", lock.voltage);
         }
     }
+
+    Ok(())
 }
 
     /*let format = table_format();
@@ -71,43 +160,60 @@ pub fn print_settings(set: &GpuSettings) {
     if show_vfp {
     }*/
 
-pub fn print_status(status: &GpuStatus) {
-    pline!("Power State", "{}", status.pstate);
-    pline!("Power Usage", "{}", 
-        status.power.iter().fold(None, |state, v| if let Some(state) = state {
+fn change(current: i64, previous: Option<i64>) -> String {
+    match previous {
+        Some(previous) if previous != current => format!(" ({:+})", current - previous),
+        _ => String::new(),
+    }
+}
+
+/// `previous` is the prior sample, used to annotate clocks, temperatures, and power with their change.
+pub fn print_status(w: &mut dyn Write, status: &GpuStatus, previous: Option<&GpuStatus>) -> io::Result<()> {
+    pline!(w, "Power State", "{}", status.pstate);
+    if let Some(voltage) = status.voltage {
+        pline!(w, "Core Voltage", "{}", voltage);
+    }
+    pline!(w, "Power Usage", "{}",
+        status.power.iter().enumerate().map(|(i, v)| format!("{}{}", v,
+            change(v.0 as i64, previous.and_then(|p| p.power.get(i)).map(|p| p.0 as i64))
+        )).fold(None, |state, v| if let Some(state) = state {
             Some(format!("{}, {}", state, v))
         } else {
-            Some(v.to_string())
+            Some(v)
         }).unwrap_or_else(n_a)
     );
-    pline!("Memory Usage", "{:.2} / {:.2} ({} evictions totalling {:.2})",
+    pline!(w, "Memory Usage", "{:.2} / {:.2} ({} evictions totalling {:.2})",
         status.memory.dedicated_available - status.memory.dedicated_available_current,
         status.memory.dedicated_available,
         status.memory.dedicated_evictions, status.memory.dedicated_evictions_size,
     );
-    pline!("Core Voltage", "{}", status.voltage.map(|v| v.to_string()).unwrap_or_else(n_a));
-    pline!("Limits", "{}",
+    pline!(w, "Limits", "{}",
         status.perf.limits.fold(None, |state, v| if let Some(state) = state {
             Some(format!("{}, {}", state, v))
         } else {
             Some(v.to_string())
-        }).unwrap_or_else(n_a)
+        }).unwrap_or_else(|| "None".into())
     );
-    pline!("VFP Lock", "{}",
+    pline!(w, "VFP Lock", "{}",
         status.vfp_locks.iter().map(|(_, v)| v).max_by_key(|v| v.0)
             .map(|v| v.to_string()).unwrap_or_else(|| "None".into())
     );
 
-    for (clock, freq) in &status.clocks {
-        pline!(format!("{} Clock", clock), "{}", freq);
+    for (clock, &frequency) in &status.clocks {
+        pline!(w, format!("{} Clock", clock), "{}{}", freq(frequency),
+            change(frequency.0 as i64 / 1000, previous.and_then(|p| p.clocks.get(clock)).map(|p| p.0 as i64 / 1000))
+        );
     }
 
     for (res, util) in &status.utilization {
-        pline!(format!("{} Load", res), "{}", util);
+        pline!(w, format!("{} Load", res), "{}", util);
     }
 
-    for &(ref sensor, ref temp) in &status.sensors {
-        pline!("Sensor", "{} ({} / {})", temp, sensor.controller, sensor.target);
+    for (i, (sensor, temp)) in status.sensors.iter().enumerate() {
+        pline!(w, "Sensor", "{}{} ({} / {})", temp,
+            change(temp.0 as i64, previous.and_then(|p| p.sensors.get(i)).map(|&(_, t)| t.0 as i64)),
+            sensor.controller, sensor.target
+        );
     }
 
     for (i, &(ref cooler, ref entry)) in status.coolers.iter().enumerate() {
@@ -123,9 +229,11 @@ pub fn print_status(status: &GpuStatus) {
         let tach = status.tachometer.as_ref()
             .and_then(|&t| if i == 0 { Some(format!(" ({} RPM)", t)) } else { None })
             .unwrap_or_else(|| String::new());
-        pline!(format!("Cooler {}", cooler.kind), "{}{}", level, tach);
-        pline!("Cooler Mode", "{}", entry.policy);
+        pline!(w, format!("Cooler {}", cooler.kind), "{}{}", level, tach);
+        pline!(w, "Cooler Mode", "{}", entry.policy);
     }
+
+    Ok(())
 }
 
 /*
@@ -199,42 +307,50 @@ pub fn print_status(status: &GpuStatus) {
     }
 */
 
-pub fn print_info(info: &GpuInfo) {
-    pline!("GPU", "{} ({})", info.name, info.codename);
-    pline!("Vendor", "{}", info.vendor);
-    pline!("GPU Shaders", "{} ({}:{} pipes)",
+pub fn print_driver(w: &mut dyn Write, driver: &DriverDescriptor) -> io::Result<()> {
+    pline!(w, "Driver Version", "{}.{:02}", driver.version / 100, driver.version % 100);
+    pline!(w, "Driver Branch", "{}", driver.branch);
+    pline!(w, "NVAPI Interface", "{}", driver.interface);
+
+    Ok(())
+}
+
+pub fn print_info(w: &mut dyn Write, info: &GpuInfo) -> io::Result<()> {
+    pline!(w, "GPU", "{} ({})", info.name, info.codename);
+    pline!(w, "Vendor", "{}", info.vendor);
+    pline!(w, "GPU Shaders", "{} ({}:{} pipes)",
         info.core_count, info.shader_pipe_count, info.shader_sub_pipe_count);
-    pline!("Video Memory", "{:.2} {}-bit",
+    pline!(w, "Video Memory", "{:.2} {}-bit",
         info.memory.dedicated, info.ram_bus_width);
-    pline!("Memory Type", "{} ({})",
+    pline!(w, "Memory Type", "{} ({})",
         info.ram_type, info.ram_maker);
-    pline!("Memory Banks", "{} ({} partitions)",
+    pline!(w, "Memory Banks", "{} ({} partitions)",
         info.ram_bank_count, info.ram_partition_count);
-    pline!("Memory Avail", "{:.2}", info.memory.dedicated_available);
-    pline!("Shared Memory", "{:.2} ({:.2} system)",
+    pline!(w, "Memory Avail", "{:.2}", info.memory.dedicated_available);
+    pline!(w, "Shared Memory", "{:.2} ({:.2} system)",
         info.memory.shared, info.memory.system);
-    pline!("Foundry", "{}", info.foundry);
-    pline!("PCI ID", "{}", info.pci);
-    pline!("BIOS Version", "{}", info.bios_version);
-    pline!("Driver Model", "{}", info.driver_model);
-    pline!("Limit Support", "{}",
+    pline!(w, "Foundry", "{}", info.foundry);
+    pline!(w, "PCI ID", "{}", info.pci);
+    pline!(w, "BIOS Version", "{}", info.bios_version);
+    pline!(w, "Driver Model", "{}", info.driver_model);
+    pline!(w, "Limit Support", "{}",
         info.perf.limits.fold(None, |state, v| if let Some(state) = state {
             Some(format!("{}, {}", state, v))
         } else {
             Some(v.to_string())
         }).unwrap_or_else(|| "None".into())
     );
-    pline!("VFP Support", "{}",
+    pline!(w, "VFP Support", "{}",
         if info.vfp_limits.is_empty() { "No" } else { "Yes" });
 
     for (_, limit) in info.power_limits.iter().enumerate() {
-        pline!("Power Limit", "{} ({} default)", limit.range, limit.default);
+        pline!(w, "Power Limit", "{} ({} default)", limit.range, limit.default);
     }
 
     for clock in ClockDomain::values() {
         if let (Some(base), boost) = (info.base_clocks.get(&clock), info.boost_clocks.get(&clock)) {
-            pline!(format!("{} Clock", clock), "{} ({} boost)",
-                base, boost.map(ToString::to_string).unwrap_or_else(n_a)
+            pline!(w, format!("{} Clock", clock), "{} ({} boost)",
+                freq(*base), boost.map(|&b| freq(b)).unwrap_or_else(n_a)
             );
         }
     }
@@ -243,16 +359,16 @@ pub fn print_info(info: &GpuInfo) {
         .zip(info.sensor_limits.iter().map(Some).chain(iter::repeat(None)))
         .enumerate()
     {
-        pline!("Thermal Sensor", "{} / {} ({} range)",
+        pline!(w, "Thermal Sensor", "{} / {} ({} range)",
             sensor.controller, sensor.target, sensor.range);
-        pline!("Thermal Limit", "{} ({} default)",
+        pline!(w, "Thermal Limit", "{} ({} default)",
             limit.map(|l| l.range.to_string()).unwrap_or_else(n_a),
             limit.map(|l| l.default.to_string()).unwrap_or_else(n_a),
         );
     }
 
     for (_, cooler) in info.coolers.iter().enumerate() {
-        pline!(format!("Cooler {}", cooler.kind), "{} / {} ({} range)",
+        pline!(w, format!("Cooler {}", cooler.kind), "{} / {} ({} range)",
             cooler.controller, cooler.target,
             match cooler.control {
                 CoolerControl::Variable => cooler.range.to_string(),
@@ -260,8 +376,10 @@ pub fn print_info(info: &GpuInfo) {
                 CoolerControl::None => n_a(),
             },
         );
-        pline!("Cooler Default", "{} Mode", cooler.default_policy);
+        pline!(w, "Cooler Default", "{} Mode", cooler.default_policy);
     }
+
+    Ok(())
 }
 
     /*let format = table_format();
@@ -332,11 +450,11 @@ pub fn print_info(info: &GpuInfo) {
         pstates.print_tty(false);
     }*/
 
-pub fn print_clocks(base: &ClockFrequencies, boost: &ClockFrequencies, current: &ClockFrequencies, util: &Utilizations) {
+pub fn print_clocks(w: &mut dyn Write, clocks: &[ClockDomain], base: &ClockFrequencies, boost: &ClockFrequencies, current: &ClockFrequencies, util: &Utilizations) -> io::Result<()> {
     let mut table = Table::new();
     table.set_format(table_format());
     table.set_titles(row!["Clock", "Usage", "Current", "Base", "Boost"]);
-    for clock in ClockDomain::values() {
+    for &clock in clocks {
         match (
             base.get(&clock), boost.get(&clock), current.get(&clock),
             UtilizationDomain::from_clock(clock).and_then(|u| util.get(&u))
@@ -346,17 +464,17 @@ pub fn print_clocks(base: &ClockFrequencies, boost: &ClockFrequencies, current:
                 table.add_row(row![
                     clock,
                     usage.map(|v| v.to_string()).unwrap_or_else(n_a),
-                    current.map(|v| v.to_string()).unwrap_or_else(n_a),
-                    base.map(|v| v.to_string()).unwrap_or_else(n_a),
-                    boost.map(|v| v.to_string()).unwrap_or_else(n_a)
+                    current.map(|&v| freq(v)).unwrap_or_else(n_a),
+                    base.map(|&v| freq(v)).unwrap_or_else(n_a),
+                    boost.map(|&v| freq(v)).unwrap_or_else(n_a)
                 ]);
             },
         }
     }
-    table.print_tty(false);
+    table.print(w)
 }
 
-pub fn print_coolers<'a, I: Iterator<Item=(&'a CoolerDesc, &'a CoolerStatus)>>(coolers: I, tach: Option<u32>) {
+pub fn print_coolers<'a, I: Iterator<Item=(&'a CoolerDesc, &'a CoolerStatus)>>(w: &mut dyn Write, coolers: I, tach: Option<u32>) -> io::Result<()> {
     let mut table = Table::new();
     table.set_format(table_format());
     table.set_titles(row!["Cooler", "Controller", "Target", "Level", "RPM", "Range", "Mode", "Default"]);
@@ -373,61 +491,241 @@ pub fn print_coolers<'a, I: Iterator<Item=(&'a CoolerDesc, &'a CoolerStatus)>>(c
         let tach = tach.and_then(|t| if i == 0 { Some(t.to_string()) } else { None }).unwrap_or_else(n_a);
         table.add_row(row![cooler.kind, cooler.controller, cooler.target, level, tach, range, status.policy, cooler.default_policy]);
     }
-    table.print_tty(false);
+    table.print(w)
+}
+
+pub fn print_test(w: &mut dyn Write, report: &TestReport) -> io::Result<()> {
+    pline!(w, "Test", "{}{}",
+        if report.passed { "Passed" } else { "Failed" },
+        if report.reverted { ", reverted" } else { "" }
+    );
+    pline!(w, "Peak Temperature", "{}", report.peak_temperature.map(|t| t.to_string()).unwrap_or_else(n_a));
+    pline!(w, "Peak Clock", "{}", report.peak_clock.map(freq).unwrap_or_else(n_a));
+    Ok(())
+}
+
+pub fn print_cooler_policies<'a, I: Iterator<Item=&'a CoolerPolicies>>(w: &mut dyn Write, coolers: I) -> io::Result<()> {
+    for cooler in coolers {
+        pline!(w, format!("{} Cooler", cooler.cooler), "{}", cooler.policies.join(", "));
+    }
+    Ok(())
 }
 
-pub fn print_sensors<'a, I: Iterator<Item=(&'a SensorDesc, Option<(&'a SensorLimit, Celsius)>, Celsius)>>(sensors: I) {
+pub fn print_sensors<'a, I: Iterator<Item=(&'a SensorDesc, Option<(&'a SensorLimit, Celsius)>, Celsius)>>(w: &mut dyn Write, sensors: I, thresholds: &TempThresholds) -> io::Result<()> {
     let mut table = Table::new();
     table.set_format(table_format());
-    table.set_titles(row!["Sensor", "Target", "Temperature", "Range", "Limit Range", "Default", "Limit"]);
+    table.set_titles(row!["Sensor", "Target", "Temperature", "Range", "Limit Range", "Default", "Limit", "Headroom"]);
     for (sensor, limit, temp) in sensors {
-        let (limit_range, limit_default, limit) = if let Some((desc, limit)) = limit {
-            (desc.range.to_string(), desc.default.to_string(), limit.to_string())
+        let current_limit = limit.map(|(_, limit)| limit);
+        let critical = thresholds.critical.or(current_limit);
+        let warn = thresholds.warn.or(current_limit.map(|limit| Celsius(limit.0 - 10)));
+        let temp_flags = if critical.is_some_and(|c| temp >= c) {
+            "!!"
+        } else if warn.is_some_and(|w| temp >= w) {
+            "!"
         } else {
-            (n_a(), n_a(), n_a())
+            ""
+        };
+
+        let (limit_range, limit_default, limit, headroom) = if let Some((desc, limit)) = limit {
+            let flags = if limit != desc.default { "*" } else { "" };
+            (desc.range.to_string(), desc.default.to_string(), format!("{}{}", limit, flags), Celsius(limit.0 - temp.0).to_string())
+        } else {
+            (n_a(), n_a(), n_a(), n_a())
         };
         table.add_row(row![
-            sensor.controller, sensor.target, temp, sensor.range,
-            limit_range, limit_default, limit
+            sensor.controller, sensor.target, format!("{}{}", temp, temp_flags), sensor.range,
+            limit_range, limit_default, limit, headroom
         ]);
     }
-    table.print_tty(false);
+    table.print(w)
 }
 
-pub fn print_vfp<I: Iterator<Item=(usize, VfPoint)>>(vfp: I, lock: Option<Microvolts>, core: Option<Microvolts>) {
+pub fn print_vfp<I: Iterator<Item=(usize, VfPoint)>>(w: &mut dyn Write, vfp: I, lock: Option<Microvolts>, core: Option<Microvolts>) -> io::Result<()> {
+    pline!(w, "VFP Lock Mode", "{}", lock.map(|v| format!("Manual ({})", v)).unwrap_or_else(|| "None".into()));
+
     let mut table = Table::new();
     table.set_format(table_format());
     table.set_titles(row!["VFP", "Voltage", "Frequency", "Offset"]);
 
+    // the core voltage rarely lands exactly on a point, mark the closest one
+    let vfp = vfp.collect::<Vec<_>>();
+    let current = core.and_then(|core| vfp.iter()
+        .min_by_key(|&(_, point)| (point.voltage.0 as i64 - core.0 as i64).abs())
+        .map(|&(i, _)| i)
+    );
+
     for (i, point) in vfp {
         let mut flags = String::new();
-        if Some(point.voltage) == core {
+        if Some(i) == current {
             flags.push('*');
         }
         if Some(point.voltage) == lock {
             flags.push('^');
         }
 
-        table.add_row(row![format!("{}{}", i, flags), point.voltage, point.frequency, point.delta]);
+        table.add_row(row![format!("{}{}", i, flags), point.voltage, freq(point.frequency), offset(point.delta)]);
     }
-    table.print_tty(false);
+    table.print(w)
 }
 
-pub fn print_pstates<'a, I: Iterator<Item=(PState, ClockDomain, &'a PStateLimit, Option<KilohertzDelta>)>>(pstates: I, current: Option<PState>) {
+pub fn print_vfp_import<'a, I: Iterator<Item=&'a VfpImportEntry>>(w: &mut dyn Write, entries: I) -> io::Result<()> {
     let mut table = Table::new();
     table.set_format(table_format());
-    table.set_titles(row!["PState", "Clock", "Frequency Range", "Offset", "Offset Limits", "Voltage"]);
+    table.set_titles(row!["VFP", "Voltage", "Offset", "Previous"]);
+
+    for entry in entries {
+        table.add_row(row![
+            entry.index.map(|i| i.to_string()).unwrap_or_else(|| "Unmatched".into()),
+            entry.voltage, offset(entry.delta),
+            entry.previous.map(offset).unwrap_or_else(n_a)
+        ]);
+    }
+    table.print(w)
+}
+
+pub fn print_vfp_locks<'a, I: Iterator<Item=&'a VfpLockEntry>>(w: &mut dyn Write, locks: I) -> io::Result<()> {
+    let locks = locks.collect::<Vec<_>>();
+    if locks.is_empty() {
+        return writeln!(w, "No VFP locks")
+    }
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!["Lock", "Mode", "Voltage", "VFP"]);
+
+    for lock in locks {
+        table.add_row(row![
+            lock.id, format!("{:?}", lock.mode), lock.voltage,
+            lock.point.map(|i| i.to_string()).unwrap_or_else(n_a)
+        ]);
+    }
+    table.print(w)
+}
+
+pub fn print_vfp_verify<'a, I: Iterator<Item=&'a VfpVerifyEntry>>(w: &mut dyn Write, entries: I) -> io::Result<()> {
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!["VFP", "Voltage", "Expected", "Actual", "Result"]);
+
+    for entry in entries {
+        table.add_row(row![
+            entry.index.map(|i| i.to_string()).unwrap_or_else(|| "Unmatched".into()),
+            entry.voltage, offset(entry.expected),
+            entry.actual.map(offset).unwrap_or_else(n_a),
+            if entry.matches { "OK" } else { "Mismatch" }
+        ]);
+    }
+    table.print(w)
+}
+
+/// `Offset Max` is the highest clock reachable within the offset limits.
+pub fn print_pstates<'a, I: Iterator<Item=(PState, ClockDomain, &'a PStateLimit, Option<KilohertzDelta>)>>(w: &mut dyn Write, pstates: I, current: Option<PState>) -> io::Result<()> {
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!["PState", "Clock", "Min", "Max", "Offset", "Offset Limits", "Offset Max", "Voltage"]);
     for (pstate, clock, limit, delta) in pstates {
         let mut flags = String::new();
         if Some(pstate) == current {
             flags.push('*');
         }
+        if delta.map(|d| d != KilohertzDelta(0)).unwrap_or(false) {
+            flags.push('+');
+        }
         table.add_row(row![
-            format!("{}{}", pstate, flags), clock, limit.frequency,
-            delta.map(|d| d.to_string()).unwrap_or_else(n_a),
-            limit.frequency_delta.map(|d| d.to_string()).unwrap_or_else(n_a),
+            format!("{}{}", pstate, flags), clock, freq(limit.frequency.min), freq(limit.frequency.max),
+            delta.map(offset).unwrap_or_else(n_a),
+            limit.frequency_delta.map(|d| format!("{} ~ {}", offset(d.min), offset(d.max))).unwrap_or_else(n_a),
+            limit.frequency_delta.map(|d| freq(limit.frequency.max + d.max)).unwrap_or_else(n_a),
             if limit.voltage_domain == VoltageDomain::Undefined { n_a() } else { limit.voltage_domain.to_string() }
         ]);
     }
-    table.print_tty(false);
+    table.print(w)
+}
+
+pub fn print_reset_plan(w: &mut dyn Write, plan: &ResetPlan) -> io::Result<()> {
+    writeln!(w, "{}", plan.name)?;
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!["Setting", "Current", "Reset To"]);
+    for setting in &plan.settings {
+        table.add_row(row![setting.setting, setting.current, setting.reset_to]);
+    }
+    table.print(w)
+}
+
+pub fn print_effective_clocks<'a, I: Iterator<Item=&'a EffectiveClock>>(w: &mut dyn Write, clocks: I) -> io::Result<()> {
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!["PState", "Clock", "Base", "Offset", "Effective"]);
+    for clock in clocks {
+        table.add_row(row![clock.pstate, clock.clock, freq(clock.base), offset(clock.delta), freq(clock.effective)]);
+    }
+    table.print(w)
+}
+
+pub fn print_setting_descriptors<'a, I: Iterator<Item=&'a SettingDescriptor>>(w: &mut dyn Write, settings: I) -> io::Result<()> {
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!["Setting", "Description"]);
+    for setting in settings {
+        table.add_row(row![setting.name, setting.description]);
+    }
+    table.print(w)
+}
+
+fn headroom_value(constraint: HeadroomConstraint, value: i64) -> String {
+    match constraint {
+        HeadroomConstraint::Power => Percentage(value as u32).to_string(),
+        HeadroomConstraint::Thermal => Celsius(value as i32).to_string(),
+        HeadroomConstraint::Clock => mhz(value),
+        HeadroomConstraint::Voltage => Microvolts(value as u32).to_string(),
+    }
+}
+
+pub fn print_headroom(w: &mut dyn Write, report: &HeadroomReport) -> io::Result<()> {
+    pline!(w, "GPU", "{}", report.name);
+    pline!(w, "Limited By", "{}", report.constraints.first().map(|c| format!("{:?}", c.constraint)).unwrap_or_else(n_a));
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!["Constraint", "Current", "Limit", "Usage"]);
+
+    for entry in &report.constraints {
+        table.add_row(row![
+            format!("{:?}", entry.constraint),
+            headroom_value(entry.constraint, entry.current),
+            headroom_value(entry.constraint, entry.limit),
+            format!("{:.1}%", entry.usage)
+        ]);
+    }
+    table.print(w)
+}
+
+pub fn print_doctor<'a, I: Iterator<Item=&'a DoctorCheck>>(w: &mut dyn Write, checks: I) -> io::Result<()> {
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!["Check", "Result", "Detail"]);
+
+    for check in checks {
+        table.add_row(row![
+            check.name,
+            match check.result {
+                DoctorResult::Pass => "OK",
+                DoctorResult::Warn => "Warning",
+                DoctorResult::Fail => "FAILED",
+            },
+            check.detail
+        ]);
+    }
+    table.print(w)
+}
+
+pub fn print_reset(w: &mut dyn Write, summary: &ResetSummary) -> io::Result<()> {
+    let settings = summary.settings.iter().map(|entry| match entry.outcome {
+        ResetOutcome::Reset => format!("{} \u{2713}", entry.setting),
+        ResetOutcome::Unsupported => format!("{} (unsupported)", entry.setting),
+        ResetOutcome::Failed => format!("{} (failed)", entry.setting),
+    }).collect::<Vec<_>>();
+    writeln!(w, "{}: {}", summary.name, settings.join(", "))
 }
@@ -8,33 +8,46 @@ extern crate quick_error;
 extern crate log;
 extern crate env_logger;
 extern crate result;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
 extern crate csv;
 
 mod auto;
+mod clocks;
 mod human;
 mod conv;
+mod curve;
+mod doctor;
 mod error;
+mod headroom;
+mod policy;
+mod privilege;
+mod schema;
+mod serve;
 mod types;
 
 use std::collections::BTreeMap;
-use std::process::exit;
-use std::thread::sleep;
-use std::time::Duration;
+use std::env;
+use std::process::{exit, Command, Stdio};
+use std::sync::mpsc;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
 use std::str::FromStr;
-use std::io::{self, Write};
-use std::{fs, iter};
+use std::io::{self, Read, Write, IsTerminal};
+use std::{cmp, fs, iter};
 use nvapi::{
-    Status, Gpu, GpuInfo, GpuSettings,
-    Percentage, Celsius, Kilohertz, KilohertzDelta, Microvolts, VfPoint,
-    ClockDomain, PState, CoolerPolicy, CoolerLevel, ClockLockMode,
+    Status, Gpu, GpuInfo, GpuStatus, GpuSettings, SensorDesc, CoolerDesc,
+    Percentage, Celsius, Kilohertz, KilohertzDelta, Microvolts, VfPoint, VfpPoint,
+    ClockDomain, PState, PStateLimit, CoolerPolicy, CoolerLevel, CoolerControl, ClockLockMode,
     allowable_result
 };
-use clap::{Arg, App, SubCommand, AppSettings};
+use clap::{Arg, App, ArgMatches, SubCommand, AppSettings};
 use result::prelude::*;
-use conv::ConvertEnum;
+use clocks::clock_json;
+use conv::{ConvertEnum, EnumArg};
 use error::Error;
 use types::*;
 
@@ -43,7 +56,12 @@ fn main() {
         Ok(code) => exit(code),
         Err(e) => {
             let _ = writeln!(io::stderr(), "{}", e);
-            exit(1);
+            if let (true, Some(status)) = (error::debug(), e.status()) {
+                let _ = writeln!(io::stderr(), "NVAPI status {:?} ({}), error_message: {:?}",
+                    status, status.raw(), nvapi::error_message(status)
+                );
+            }
+            exit(e.exit_code());
         },
     }
 }
@@ -52,20 +70,537 @@ fn is_std(str: &str) -> bool {
     str == "-"
 }
 
-fn export_vfp<W: Write, I: Iterator<Item=VfPoint>>(write: W, points: I, delimiter: u8) -> io::Result<()> {
-    let mut w = csv::WriterBuilder::new().delimiter(delimiter).from_writer(write);
+/// Name used to address a thermal sensor on the command line, derived from its target (core, memory, vrm...)
+fn sensor_name(sensor: &SensorDesc) -> String {
+    sensor.target.to_string().to_lowercase().replace(' ', "-")
+}
+
+/// The index of the thermal sensor `sensor` names, by index or by a name from `names`
+fn sensor_index(names: &[String], sensor: &str) -> Result<usize, Error> {
+    if let Ok(index) = usize::from_str(sensor) {
+        return if index < names.len() {
+            Ok(index)
+        } else {
+            Err(Error::from(format!("no thermal sensor {}, there are {}", index, names.len())))
+        }
+    }
+
+    let matching = names.iter().enumerate().filter(|&(_, n)| n == sensor).map(|(i, _)| i).collect::<Vec<_>>();
+    match matching.len() {
+        0 => Err(Error::from(format!(
+            "unknown thermal sensor \"{}\", expected one of: {}", sensor, names.join(", ")
+        ))),
+        1 => Ok(matching[0]),
+        _ => Err(Error::from(format!(
+            "thermal sensor \"{}\" is ambiguous, use its index instead: {}",
+            sensor, matching.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+fn cooler_name(cooler: &CoolerDesc) -> String {
+    cooler.target.to_string().to_lowercase().replace(' ', "-")
+}
+
+fn export_vfp<W: Write, I: Iterator<Item=VfPoint>>(write: W, points: I, format: VfpFormat, delimiter: u8, headers: bool) -> io::Result<()> {
+    match format {
+        VfpFormat::Csv => {
+            let mut w = csv::WriterBuilder::new().delimiter(delimiter).has_headers(headers).from_writer(write);
+
+            for point in points {
+                w.serialize(VfpRow::from(point))?;
+            }
+            w.flush()
+        },
+        VfpFormat::Json => {
+            let mut write = write;
+            serde_json::to_writer_pretty(&mut write, &points.collect::<Vec<_>>())?;
+            writeln!(write)
+        },
+    }
+}
+
+/// Reads the GPU status, retrying with backoff when NVAPI reports a transient failure.
+fn status_retry(gpu: &Gpu, retries: u32) -> Result<GpuStatus, Status> {
+    let mut delay = Duration::from_millis(250);
+    let mut attempt = 0;
+
+    loop {
+        match gpu.status() {
+            Err(e @ Status::DeviceBusy) | Err(e @ Status::HandleInvalidated) |
+            Err(e @ Status::Timeout) | Err(e @ Status::TimeoutReconfiguringGpuTopo) if attempt < retries => {
+                attempt += 1;
+                warn!("Failed to read GPU status ({:?}), retrying {}/{}", e, attempt, retries);
+                sleep(delay);
+                delay *= 2;
+            },
+            res => return res,
+        }
+    }
+}
+
+/// Parses a frequency in MHz, or as a percentage of `reference` when suffixed with `%`
+fn parse_frequency(value: &str, reference: Option<Kilohertz>) -> Result<Kilohertz, Error> {
+    if let Some(percent) = value.strip_suffix('%') {
+        let percent = f64::from_str(percent)?;
+        let reference = reference.ok_or(Error::Str("boost clock unavailable, specify the frequency in MHz"))?;
+        Ok(Kilohertz((reference.0 as f64 * percent / 100.0) as u32))
+    } else {
+        Ok(Kilohertz(u32::from_str(value)? * 1000))
+    }
+}
+
+/// Parses a time such as `30s`, `5m` or `1h`, bare numbers are seconds
+fn parse_duration(value: &str) -> Result<Duration, Error> {
+    let (value, scale) = if let Some(value) = value.strip_suffix("ms") {
+        (value, 0.001)
+    } else if let Some(value) = value.strip_suffix('s') {
+        (value, 1.0)
+    } else if let Some(value) = value.strip_suffix('m') {
+        (value, 60.0)
+    } else if let Some(value) = value.strip_suffix('h') {
+        (value, 3600.0)
+    } else {
+        (value, 1.0)
+    };
+
+    let secs = f64::from_str(value)? * scale;
+    if secs < 0.0 {
+        return Err(Error::Str("duration can't be negative"))
+    }
+    Ok(Duration::new(secs as u64, (secs.fract() * 1e9) as u32))
+}
+
+/// Runs a stress test command line to completion, sampling the GPU while it runs
+///
+/// Every sample is also written to the `samples` CSV file when given. The command's
+/// stdout goes to stderr instead when `quiet` is set, so it can't mix into JSON output.
+fn run_test(gpu: &Gpu, command: &str, samples: Option<&str>, quiet: bool) -> Result<TestReport, Error> {
+    let mut samples = samples.map(|path| fs::File::create(path).map(csv::Writer::from_writer)).invert()?;
+
+    let args = split_args(command)?;
+    let (program, args) = args.split_first().ok_or(Error::Str("empty test command"))?;
+    let mut child = Command::new(program);
+    child.args(args);
+    if quiet {
+        child.stdout(Stdio::from(io::stderr()));
+    }
+    let mut child = child.spawn()?;
+    let start = Instant::now();
+
+    let mut peak_temperature = None;
+    let mut peak_clock = None;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status
+        }
+
+        match gpu.status() {
+            Ok(status) => {
+                let temp = status.sensors.iter().map(|&(_, temp)| temp).max();
+                let clock = status.clocks.get(&ClockDomain::Graphics).cloned();
+                peak_temperature = cmp::max(peak_temperature, temp);
+                peak_clock = cmp::max(peak_clock, clock);
+
+                if let Some(ref mut samples) = samples {
+                    samples.serialize(TestSample {
+                        elapsed: start.elapsed().as_millis() as u64,
+                        temperature: temp,
+                        clock,
+                        power: status.power.first().cloned(),
+                        voltage: status.voltage,
+                    }).map_err(io::Error::from)?;
+                }
+            },
+            Err(e) => warn!("Failed to read GPU status during the test: {}", Error::from(e)),
+        }
+
+        sleep(Duration::from_millis(500));
+    };
+
+    if let Some(ref mut samples) = samples {
+        samples.flush()?;
+    }
 
-    Ok(for point in points {
-        w.serialize(point)?;
+    info!("{} exited with {}", command, status);
+    Ok(TestReport {
+        passed: status.success(),
+        peak_temperature,
+        peak_clock,
+        reverted: false,
     })
 }
 
-fn main_result() -> Result<i32, Error> {
-    if let Err(e) = env_logger::init() {
-        let _ = writeln!(io::stderr(), "Failed to initialize env_logger: {}", e);
+/// `--and-test`, run after a change to the GPU at `index`, calling `revert` when the test fails and `--revert` was given
+fn and_test<F: FnOnce() -> Result<(), Error>>(matches: &ArgMatches, gpu: &Gpu, index: usize, several: bool, oformat: OutputFormat, revert: F) -> Result<Option<TestReport>, Error> {
+    let command = match matches.value_of("and_test") {
+        Some(command) => command,
+        None => return Ok(None),
+    };
+
+    let samples = matches.value_of("sample_csv")
+        .map(|path| if several { gpu_output_path(path, index) } else { path.to_owned() });
+    let mut report = run_test(gpu, command, samples.as_ref().map(|p| &p[..]), oformat == OutputFormat::Json)?;
+    if !report.passed && matches.is_present("revert") {
+        revert()?;
+        report.reverted = true;
+    }
+
+    Ok(Some(report))
+}
+
+/// Prints the `--and-test` report of each GPU that ran one
+fn print_test_reports(out: &mut dyn Write, oformat: OutputFormat, reports: Vec<(usize, TestReport)>, keyed: bool, clock_unit: ClockUnit) -> Result<(), Error> {
+    if reports.is_empty() {
+        return Ok(())
+    }
+
+    match oformat {
+        OutputFormat::Human => for (_, report) in &reports {
+            human::print_test(out, report)?;
+        },
+        OutputFormat::Json => {
+            let (indices, reports): (Vec<_>, Vec<_>) = reports.into_iter().unzip();
+            serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&reports, clock_unit)?, &indices, keyed))?
+        },
+    }
+
+    Ok(())
+}
+
+/// Checks that `clock` has an adjustable offset at `pstate`, the driver silently ignores offsets to other clocks
+fn check_pstate_clock(clocks: &BTreeMap<ClockDomain, PStateLimit>, pstate: PState, clock: ClockDomain) -> Result<(), Error> {
+    match clocks.get(&clock) {
+        Some(limit) if limit.frequency_delta.is_some() => Ok(()),
+        _ => {
+            let adjustable = clocks.iter()
+                .filter(|&(_, limit)| limit.frequency_delta.is_some())
+                .map(|(clock, _)| clock.to_str())
+                .collect::<Vec<_>>();
+            Err(Error::from(format!("{} clock has no adjustable offset at {}, expected one of: {}",
+                clock.to_str(), pstate, if adjustable.is_empty() { "none".into() } else { adjustable.join(", ") }
+            )))
+        },
+    }
+}
+
+/// A comma separated list of exit codes, e.g. `--fail-codes 1,3`
+fn exit_codes(matches: &ArgMatches, arg: &str) -> Result<Option<Vec<i32>>, Error> {
+    matches.value_of(arg).map(|v| v.split(',').map(|c| i32::from_str(c.trim())).collect::<Result<Vec<_>, _>>()).invert().map_err(From::from)
+}
+
+/// Splits a `KEY=VALUE` environment assignment
+fn parse_env(value: &str) -> Result<(String, String), Error> {
+    match value.find('=') {
+        Some(i) if i > 0 => Ok((value[..i].to_owned(), value[i + 1..].to_owned())),
+        _ => Err(Error::from(format!("expected KEY=VALUE, got \"{}\"", value))),
+    }
+}
+
+/// Formats a duration to the second, e.g. `1h02m03s`
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+/// Parses a power limit in %, or in watts when suffixed with `w` given the board's `tdp` (its 100% power)
+fn parse_power_limit(value: &str, tdp: Option<f64>) -> Result<Percentage, Error> {
+    if value.ends_with('w') || value.ends_with('W') {
+        let watts = f64::from_str(&value[..value.len() - 1])?;
+        let tdp = tdp.ok_or(Error::Str("NVAPI doesn't report the board power in watts, pass --tdp to set the power limit in watts"))?;
+        Ok(Percentage((watts * 100.0 / tdp).round() as u32))
+    } else {
+        Ok(Percentage(u32::from_str(value)?))
+    }
+}
+
+fn export_auto<'a, W: Write, I: Iterator<Item=&'a AutoResultRow>>(write: W, rows: I) -> io::Result<()> {
+    let mut w = csv::Writer::from_writer(write);
+
+    for row in rows {
+        w.serialize(row)?;
+    }
+
+    Ok(())
+}
+
+fn extension(path: &str) -> Option<String> {
+    ::std::path::Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase())
+}
+
+/// `--format`, or when it wasn't given, the format implied by the file extension of `path`
+fn vfp_format(matches: &ArgMatches, path: &str) -> Result<VfpFormat, Error> {
+    if matches.occurrences_of("format") == 0 {
+        match extension(path).as_ref().map(|e| &e[..]) {
+            Some("json") => return Ok(VfpFormat::Json),
+            Some("csv") | Some("tsv") => return Ok(VfpFormat::Csv),
+            _ => (),
+        }
+    }
+
+    matches.value_of("format").map(VfpFormat::from_str).unwrap()
+}
+
+/// Environment variable holding the default output format
+const OUTPUT_FORMAT_ENV: &str = "NVOCLOCK_OUTPUT_FORMAT";
+
+fn output_format(matches: &ArgMatches) -> Result<OutputFormat, Error> {
+    let cli = if matches.occurrences_of("oformat") > 0 { matches.value_of("oformat") } else { None };
+    let env = env::var(OUTPUT_FORMAT_ENV).ok();
+
+    resolve_output_format(cli, env.as_ref().map(|e| &e[..]), matches.value_of("out"))
+}
+
+/// The first of `-O`, `NVOCLOCK_OUTPUT_FORMAT`, JSON when `--out` names a .json file, or human
+fn resolve_output_format(cli: Option<&str>, env: Option<&str>, out: Option<&str>) -> Result<OutputFormat, Error> {
+    if let Some(format) = cli {
+        return OutputFormat::from_str(format)
+    }
+
+    if let Some(format) = env {
+        return OutputFormat::from_str(format).map_err(|e| Error::from(format!("{}: {}", OUTPUT_FORMAT_ENV, e)))
+    }
+
+    if out.and_then(extension).as_ref().map(|e| &e[..]) == Some("json") {
+        return Ok(OutputFormat::Json)
+    }
+
+    Ok(OutputFormat::Human)
+}
+
+/// Turns an array of per-GPU values into an object keyed by GPU index when `keyed` is set
+fn gpu_json(value: serde_json::Value, indices: &[usize], keyed: bool) -> serde_json::Value {
+    match value {
+        serde_json::Value::Array(values) if keyed => serde_json::Value::Object(
+            indices.iter().map(|i| i.to_string()).zip(values).collect()
+        ),
+        value => value,
+    }
+}
+
+/// CSV delimiter from `--tabs`/`--delimiter`, or else a tab for a .tsv `path` and a comma otherwise
+fn csv_delimiter(matches: &ArgMatches, path: &str) -> Result<u8, Error> {
+    if matches.is_present("tabs") {
+        return Ok(b'\t')
+    }
+
+    match matches.value_of("delimiter") {
+        Some(d) if d.len() == 1 && d.is_ascii() => Ok(d.as_bytes()[0]),
+        Some(d) => Err(Error::from(format!("delimiter must be a single ASCII character, got \"{}\"", d))),
+        None => Ok(match extension(path).as_ref().map(|e| &e[..]) {
+            Some("tsv") => b'\t',
+            _ => b',',
+        }),
+    }
+}
+
+/// The active curve locks, matched up with the curve point at their voltage
+fn vfp_locks(gpu: &Gpu) -> Result<Vec<VfpLockEntry>, Error> {
+    let vfp = gpu.status()?.vfp.map(|vfp| vfp.graphics).unwrap_or_default();
+
+    Ok(gpu.settings()?.vfp_locks.into_iter()
+        .filter(|(_, lock)| lock.mode != ClockLockMode::None)
+        .map(|(id, lock)| VfpLockEntry {
+            id,
+            mode: lock.mode,
+            voltage: lock.voltage,
+            point: vfp.iter().find(|(_, p)| p.voltage == lock.voltage).map(|(&i, _)| i),
+        }).collect())
+}
+
+/// Lists the active curve locks of each GPU, see `vfp locks` and `vfp unlock --list`
+fn print_vfp_locks(out: &mut dyn Write, oformat: OutputFormat, gpus: &[&Gpu], indices: &[usize], keyed: bool) -> Result<(), Error> {
+    let locks = gpus.iter().map(|gpu| vfp_locks(gpu)).collect::<Result<Vec<_>, _>>()?;
+
+    match oformat {
+        OutputFormat::Human => for locks in &locks {
+            human::print_vfp_locks(out, locks.iter())?;
+        },
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &gpu_json(serde_json::to_value(&locks)?, indices, keyed))?
+        },
+    }
+
+    Ok(())
+}
+
+/// Compares curve points read from a file against the offsets applied to the live curve
+///
+/// `tolerance` is in kHz.
+fn verify_vfp(vfp: &BTreeMap<usize, VfpPoint>, deltas: &BTreeMap<usize, KilohertzDelta>, input: &[VfPoint], tolerance: i32) -> Vec<VfpVerifyEntry> {
+    input.iter().map(|point| {
+        let index = vfp.iter()
+            .find(|(_, v)| v.voltage == point.voltage)
+            .map(|(&i, _)| i);
+        let actual = index.and_then(|i| deltas.get(&i).cloned());
+
+        VfpVerifyEntry {
+            index,
+            voltage: point.voltage,
+            expected: point.delta,
+            actual,
+            matches: actual.map(|d| (d.0 - point.delta.0).abs() <= tolerance).unwrap_or(false),
+        }
+    }).collect()
+}
+
+/// Output path for one of several GPUs, substituting `{gpu}` or else suffixing the file stem with the index
+fn gpu_output_path(output: &str, index: usize) -> String {
+    if output.contains("{gpu}") {
+        return output.replace("{gpu}", &index.to_string())
+    }
+
+    let path = ::std::path::Path::new(output);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => path.with_file_name(format!("{}-{}.{}", stem.to_string_lossy(), index, ext.to_string_lossy()))
+            .to_string_lossy().into_owned(),
+        _ => format!("{}-{}", output, index),
+    }
+}
+
+/// Settings picked by `reset`, and whether they were asked for explicitly rather than defaulting to all of them
+fn reset_settings(matches: &ArgMatches) -> Result<(Vec<ResetSettings>, bool), Error> {
+    let (mut settings, explicit) = if let Some(reset) = matches.values_of("setting") {
+        (reset.map(ResetSettings::from_str).collect::<Result<Vec<_>, _>>()?, true)
+    } else if matches.is_present("pstate") {
+        (vec![ResetSettings::PStateDeltas], true)
+    } else {
+        (ResetSettings::possible_values_typed().to_vec(), false)
+    };
+
+    settings.sort_by_key(|s| s.order());
+    settings.dedup();
+    Ok((settings, explicit))
+}
+
+fn pstate_filter(matches: &ArgMatches) -> Result<Option<Vec<PState>>, Error> {
+    matches.values_of("pstate").map(|v| v.map(PState::from_str).collect()).invert()
+}
+
+/// Offsets that resetting `setting` should have cleared but are still applied, `reset` reads them back to check
+fn leftover_offsets(set: &GpuSettings, setting: ResetSettings, pstates: Option<&[PState]>) -> usize {
+    match setting {
+        ResetSettings::VfpDeltas => set.vfp.as_ref()
+            .map(|vfp| vfp.graphics.values().chain(vfp.memory.values()).filter(|&&d| d != KilohertzDelta(0)).count())
+            .unwrap_or(0),
+        ResetSettings::PStateDeltas => set.pstate_deltas.iter()
+            .filter(|&(pstate, _)| pstates.is_none_or(|p| p.contains(pstate)))
+            .flat_map(|(_, clocks)| clocks.values())
+            .filter(|&&d| d != KilohertzDelta(0))
+            .count(),
+        _ => 0,
+    }
+}
+
+/// Whether a CSV field is a number with a decimal point or comma
+fn is_fractional(field: &str) -> bool {
+    let field = field.trim().trim_start_matches(['-', '+']);
+    field.contains(['.', ',']) && field.chars().all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+}
+
+fn import_vfp<R: io::Read>(read: R, format: VfpFormat, delimiter: u8, headers: bool) -> io::Result<Vec<VfPoint>> {
+    match format {
+        VfpFormat::Csv => {
+            let mut csv = csv::ReaderBuilder::new().delimiter(delimiter).has_headers(headers).from_reader(read);
+            let headers = if headers { Some(csv.headers()?.clone()) } else { None };
+
+            csv.records().map(|record| {
+                let record = record?;
+                // spreadsheets in some locales write 837,5 for 837.5, point at the row instead of a generic parse error
+                if let Some(field) = record.iter().find(|f| is_fractional(f)) {
+                    let line = record.position().map(|p| p.line()).unwrap_or(0);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                        "line {}: \"{}\" isn't a whole number, values are integer uV and kHz (check for decimal commas)",
+                        line, field
+                    )))
+                }
+
+                record.deserialize::<VfpRow>(headers.as_ref()).map(VfPoint::from).map_err(From::from)
+            }).collect()
+        },
+        VfpFormat::Json => serde_json::from_reader(read).map_err(From::from),
+    }
+}
+
+fn enforce_monotonic(curve: &BTreeMap<usize, VfPoint>, deltas: &mut BTreeMap<usize, KilohertzDelta>, strict: bool) -> Result<(), Error> {
+    let adjusted = curve::enforce_monotonic(curve, deltas);
+
+    if strict && !adjusted.is_empty() {
+        let points = adjusted.iter().map(|&(i, _)| i.to_string()).collect::<Vec<_>>();
+        return Err(Error::from(format!("Curve frequency decreases at points {}", points.join(", "))))
+    }
+
+    for (i, previous) in adjusted {
+        warn!("Lowered point {} from {} to {} to keep the curve monotonic", i, previous, deltas[&i]);
+    }
+
+    Ok(())
+}
+
+/// Puts a GPU's coolers back to the levels they had when this was created, once dropped.
+struct CoolerRestore<'a> {
+    gpu: &'a Gpu,
+    levels: Vec<CoolerLevel>,
+}
+
+impl<'a> CoolerRestore<'a> {
+    fn new(gpu: &'a Gpu) -> Result<Self, Error> {
+        Ok(CoolerRestore {
+            levels: gpu.status()?.coolers.iter().map(|(_, cooler)| CoolerLevel {
+                policy: cooler.policy,
+                level: cooler.level,
+            }).collect(),
+            gpu,
+        })
+    }
+}
+
+impl<'a> Drop for CoolerRestore<'a> {
+    fn drop(&mut self) {
+        info!("Restoring cooler levels");
+        if let Err(err) = self.gpu.set_cooler_levels(self.levels.iter().cloned()) {
+            error!("Failed to restore cooler levels: {}", Error::from(err));
+        }
+    }
+}
+
+/// Whether the command changes GPU settings and so needs elevation
+fn writes(matches: &ArgMatches) -> bool {
+    match matches.subcommand() {
+        ("reset", Some(matches)) => !matches.is_present("list") && !matches.is_present("dry_run"),
+        ("set", Some(matches)) => match matches.subcommand() {
+            ("vfp", Some(matches)) => match matches.subcommand() {
+                ("export", _) | ("verify", _) | ("snapshot", _) | ("locks", _) => false,
+                ("import", Some(matches)) => !matches.is_present("dry_run"),
+                _ => true,
+            },
+            ("cooler", Some(matches)) => matches.subcommand_name() != Some("policies"),
+            _ => true,
+        },
+        // these run `set` commands of their own, check up front rather than partway through
+        ("apply-env", _) | ("batch", _) | ("guard", _) => true,
+        _ => false,
     }
+}
+
+/// Bulky `status` JSON fields dropped by `--summarize`
+const SUMMARY_OMITS: &[&str] = &["vfp", "vfp_locks", "voltage_domains", "voltage_step", "voltage_table"];
+
+/// Environment variables read by `apply-env`, and the command each value is appended to
+const APPLY_ENV: &[(&str, &[&str])] = &[
+    ("NVOCLOCK_VOLTAGE_BOOST", &["set", "--voltage-boost"]),
+    ("NVOCLOCK_POWER_LIMIT", &["set", "--power-limit"]),
+    ("NVOCLOCK_THERMAL_LIMIT", &["set", "--thermal-limit"]),
+    ("NVOCLOCK_PSTATE_OFFSET", &["set", "pstate"]),
+    ("NVOCLOCK_COOLER", &["set", "cooler", "--persist"]),
+];
 
-    let app = App::new("newclock")
+fn app() -> App<'static, 'static> {
+    App::new("newclock")
         .version(env!("CARGO_PKG_VERSION"))
         .author("arcnmx")
         .about("NVIDIA overclocking")
@@ -75,19 +610,47 @@ fn main_result() -> Result<i32, Error> {
             .value_name("GPU")
             .takes_value(true)
             .multiple(true)
-            .help("GPU index")
+            .help("GPU index, or name as shown by list")
         ).arg(Arg::with_name("oformat")
             .short("O")
             .long("output-format")
             .value_name("OFORMAT")
             .takes_value(true)
-            .possible_values(OutputFormat::possible_values())
+            .enum_values::<OutputFormat>()
             .default_value(OutputFormat::Human.to_str())
-            .help("Data output format")
+            .enum_help::<OutputFormat>("Data output format")
+        ).arg(Arg::with_name("clock_unit")
+            .long("clock-unit")
+            .value_name("UNIT")
+            .takes_value(true)
+            .enum_values::<ClockUnit>()
+            .default_value(ClockUnit::Khz.to_str())
+            .enum_help::<ClockUnit>("Unit for clocks in JSON output, human output always uses MHz")
+        ).arg(Arg::with_name("json_keyed")
+            .long("json-keyed")
+            .help("Key per-GPU JSON output by GPU index instead of emitting an array")
+        ).arg(Arg::with_name("out")
+            .long("out")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("-")
+            .help("Write command output to a file instead of stdout")
+        ).arg(Arg::with_name("debug")
+            .long("debug")
+            .help("Include raw NVAPI status codes in error messages")
         ).subcommand(SubCommand::with_name("list")
             .about("List detected GPUs")
+            .arg(Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Include GPU ID, PCI identifiers, memory size and VBIOS version")
+            )
         ).subcommand(SubCommand::with_name("info")
             .about("Information about the model and capabilities of the GPU")
+            .arg(Arg::with_name("driver")
+                .long("driver")
+                .help("Show the NVIDIA driver and NVAPI interface versions instead")
+            )
         ).subcommand(SubCommand::with_name("status")
             .about("Show current GPU usage, sensor, and clock information")
             .arg(Arg::with_name("all")
@@ -110,6 +673,14 @@ fn main_result() -> Result<i32, Error> {
                 .possible_values(POSSIBLE_BOOL)
                 .default_value(POSSIBLE_BOOL_ON)
                 .help("Show clock frequency info")
+            ).arg(Arg::with_name("clock")
+                .long("clock")
+                .value_name("CLOCK")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .enum_values::<ClockDomain>()
+                .enum_help::<ClockDomain>("Only show the given clock domain")
             ).arg(Arg::with_name("coolers")
                 .short("C")
                 .long("coolers")
@@ -120,7 +691,7 @@ fn main_result() -> Result<i32, Error> {
                 .default_value_if("all", None, POSSIBLE_BOOL_ON)
                 .help("Show cooler info")
             ).arg(Arg::with_name("sensors")
-                .short("s")
+                .short("S")
                 .long("sensors")
                 .value_name("SHOW")
                 .takes_value(true)
@@ -152,17 +723,127 @@ fn main_result() -> Result<i32, Error> {
                 .value_name("PERIOD")
                 .takes_value(true)
                 .help("Monitor GPU status over time, optionally accepts period in seconds")
+            ).arg(Arg::with_name("retries")
+                .long("retries")
+                .value_name("COUNT")
+                .takes_value(true)
+                .help("Retry reading status this many times on transient errors [default: 3 with --monitor, otherwise 0]")
+            ).arg(Arg::with_name("delta")
+                .long("delta")
+                .requires("monitor")
+                .help("Show how clocks, temperatures and power changed since the previous sample")
+            ).arg(Arg::with_name("duration")
+                .long("duration")
+                .value_name("TIME")
+                .takes_value(true)
+                .requires("monitor")
+                .help("Stop monitoring after this long, e.g. 30s or 5m")
+            ).arg(Arg::with_name("fail_over_temp")
+                .long("fail-over-temp")
+                .value_name("CELSIUS")
+                .takes_value(true)
+                .help("Exit with an error if any sensor is above this temperature")
+            ).arg(Arg::with_name("fail_over_power")
+                .long("fail-over-power")
+                .value_name("PERCENT")
+                .takes_value(true)
+                .help("Exit with an error if power usage is above this percentage")
+            ).arg(Arg::with_name("sparklines")
+                .long("sparklines")
+                .requires("monitor")
+                .help("Show recent temperature, clock and load trends, plain ranges when not writing to a terminal")
+            ).arg(Arg::with_name("summarize")
+                .long("summarize")
+                .help("Leave the VFP curve and voltage tables out of JSON output")
+            ).arg(Arg::with_name("warn_temp")
+                .long("warn-temp")
+                .value_name("CELSIUS")
+                .takes_value(true)
+                .help("Flag sensors at or above this temperature with !, defaults to 10C under the limit")
+            ).arg(Arg::with_name("crit_temp")
+                .long("crit-temp")
+                .value_name("CELSIUS")
+                .takes_value(true)
+                .help("Flag sensors at or above this temperature with !!, defaults to the limit")
+            )
+        ).subcommand(SubCommand::with_name("headroom")
+            .about("Show how close each GPU is to its power, thermal, clock and voltage limits")
+        ).subcommand(SubCommand::with_name("serve")
+            .about("Serve read-only JSON over HTTP at /gpus, /gpus/{i}/info and /gpus/{i}/status")
+            .arg(Arg::with_name("listen")
+                .long("listen")
+                .value_name("ADDR")
+                .takes_value(true)
+                .default_value("127.0.0.1:8080")
+                .help("Address to listen on")
+            )
+        ).subcommand(SubCommand::with_name("dump")
+            .about("Print the info, status and settings of each GPU as a single JSON document")
+        ).subcommand(SubCommand::with_name("schema")
+            .about("Print the JSON Schema of a command's JSON output, as shaped by --json-keyed and --clock-unit")
+            .setting(AppSettings::Hidden)
+            .arg(Arg::with_name("kind")
+                .value_name("KIND")
+                .takes_value(true)
+                .required(true)
+                .enum_values::<SchemaKind>()
+                .enum_help::<SchemaKind>("Output to describe")
             )
         ).subcommand(SubCommand::with_name("get")
             .about("Show GPU overclock settings")
+            .arg(Arg::with_name("only")
+                .long("only")
+                .value_name("FIELD")
+                .takes_value(true)
+                .enum_values::<SettingField>()
+                .enum_help::<SettingField>("Only print the value of a single setting")
+            ).arg(Arg::with_name("pstate")
+                .short("p")
+                .long("pstate")
+                .value_name("PSTATE")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .enum_values::<PState>()
+                .enum_help::<PState>("Only show offsets for the given pstate(s)")
+            ).arg(Arg::with_name("effective")
+                .long("effective")
+                .conflicts_with("only")
+                .help("Show each pstate's maximum clock with its offset applied")
+            ).arg(Arg::with_name("tdp")
+                .long("tdp")
+                .value_name("WATTS")
+                .takes_value(true)
+                .help("Board power in watts at a 100% power limit, to also show power limits in watts")
+            )
         ).subcommand(SubCommand::with_name("reset")
             .about("Restore all overclocking settings")
             .arg(Arg::with_name("setting")
                 .value_name("SETTING")
                 .takes_value(true)
                 .multiple(true)
-                .possible_values(ResetSettings::possible_values())
-                .help("Reset only the specified setting(s)")
+                .enum_values::<ResetSettings>()
+                .enum_help::<ResetSettings>("Reset only the specified setting(s)")
+            ).arg(Arg::with_name("list")
+                .short("l")
+                .long("list")
+                .conflicts_with("setting")
+                .help("List the available settings without resetting anything")
+            ).arg(Arg::with_name("pstate")
+                .short("p")
+                .long("pstate")
+                .value_name("PSTATE")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .enum_values::<PState>()
+                .conflicts_with("list")
+                .enum_help::<PState>("Only clear offsets of the given pstate(s), implies the pstate setting")
+            ).arg(Arg::with_name("dry_run")
+                .short("n")
+                .long("dry-run")
+                .conflicts_with("list")
+                .help("Show the current value of each setting and what it would be reset to, without resetting")
             )
         ).subcommand(SubCommand::with_name("set")
             .about("GPU overclocking")
@@ -171,21 +852,27 @@ fn main_result() -> Result<i32, Error> {
                 .long("voltage-boost")
                 .value_name("VBOOST")
                 .takes_value(true)
-                .help("Voltage Boost %")
+                .help("Voltage Boost %, or \"reset\" to clear it")
             ).arg(Arg::with_name("tlimit")
                 .short("T")
                 .long("thermal-limit")
                 .value_name("TEMPLIMIT")
                 .takes_value(true)
                 .multiple(true)
-                .help("Thermal limit (C)")
+                .help("Thermal limit (C), optionally targeting a sensor by name or index (gpu=90, 1=85)")
             ).arg(Arg::with_name("plimit")
                 .short("P")
                 .long("power-limit")
                 .value_name("POWERLIMIT")
                 .takes_value(true)
                 .multiple(true)
-                .help("Power limit %")
+                .help("Power limit %, may be below 100 where the GPU allows it, or watts with a w suffix (requires --tdp)")
+            ).arg(Arg::with_name("tdp")
+                .long("tdp")
+                .value_name("WATTS")
+                .takes_value(true)
+                .requires("plimit")
+                .help("Board power in watts at a 100% power limit, used to convert --power-limit watts")
             ).subcommand(SubCommand::with_name("pstate")
                 .about("Simple offset overclocking")
                 .arg(Arg::with_name("pstate")
@@ -193,78 +880,330 @@ fn main_result() -> Result<i32, Error> {
                     .long("pstate")
                     .value_name("PSTATE")
                     .takes_value(true)
-                    .possible_values(PState::possible_values())
+                    .enum_values::<PState>()
                     .default_value(PState::P0.to_str())
-                    .help("PState number")
+                    .enum_help::<PState>("PState number")
                 ).arg(Arg::with_name("clock")
                     .short("c")
                     .long("clock")
                     .value_name("CLOCK")
                     .takes_value(true)
-                    .possible_values(ClockDomain::possible_values())
+                    .enum_values::<ClockDomain>()
                     .default_value(ClockDomain::Graphics.to_str())
-                    .help("Clock type")
+                    .enum_help::<ClockDomain>("Clock type")
                 ).arg(Arg::with_name("delta")
                     .value_name("DELTA")
                     .takes_value(true)
                     .allow_hyphen_values(true)
                     .required(true)
-                    .help("Clock delta (MHz)")
+                    .help("Clock delta (MHz), prefix with += or -= to adjust the current offset")
+                ).arg(Arg::with_name("and_test")
+                    .long("and-test")
+                    .value_name("COMMAND")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .help("Run this stress test once the offset is applied and report the result, its output goes to stderr with -O json")
+                ).arg(Arg::with_name("revert")
+                    .long("revert-on-fail")
+                    .requires("and_test")
+                    .help("Restore the previous offset if the test fails")
+                ).arg(Arg::with_name("sample_csv")
+                    .long("sample-to-csv")
+                    .value_name("FILE")
+                    .takes_value(true)
+                    .requires("and_test")
+                    .help("Record temperature, clock, power and voltage samples taken during the test, {gpu} is replaced by the GPU index when several are selected")
                 )
             ).subcommand(SubCommand::with_name("cooler")
-                .about("Fan and cooler controls")
+                .about("Fan and cooler controls, only the first cooler is affected unless --all, --index or --name is given. From a terminal the command blocks until enter is pressed and then restores the previous policy, unless --persist is given")
                 .arg(Arg::with_name("policy")
                     .value_name("MODE")
                     .takes_value(true)
-                    .required(true)
-                    .possible_values(CoolerPolicy::possible_values())
-                    .help("Cooler policy")
+                    .requires("level")
+                    .enum_values::<CoolerPolicy>()
+                    .enum_help::<CoolerPolicy>("Cooler policy")
                 ).arg(Arg::with_name("level")
                     .value_name("LEVEL")
                     .takes_value(true)
-                    .required(true)
                     .help("Cooler level %")
+                ).arg(Arg::with_name("all")
+                    .short("a")
+                    .long("all")
+                    .help("Apply the policy and level to every cooler")
+                ).arg(Arg::with_name("index")
+                    .short("i")
+                    .long("index")
+                    .value_name("INDEX")
+                    .takes_value(true)
+                    .conflicts_with("all")
+                    .help("Apply the policy and level to a single cooler")
+                ).arg(Arg::with_name("name")
+                    .short("n")
+                    .long("name")
+                    .value_name("NAME")
+                    .takes_value(true)
+                    .conflicts_with_all(&["all", "index"])
+                    .help("Apply the policy and level to the cooler with this target (core, memory, vrm...)")
+                ).arg(Arg::with_name("auto_restore")
+                    .long("auto-restore")
+                    .help("Block until enter is pressed, then restore the previous cooler policy (default when run from a terminal, requires one)")
+                ).arg(Arg::with_name("persist")
+                    .long("persist")
+                    .conflicts_with("auto_restore")
+                    .help("Keep the new cooler policy after exiting")
+                ).subcommand(SubCommand::with_name("policies")
+                    .about("List the cooler policies each cooler accepts")
+                ).subcommand(SubCommand::with_name("characterize")
+                    .about("Step the cooler level and record the fan speed reached at each step as CSV")
+                    .arg(Arg::with_name("min")
+                        .long("min")
+                        .value_name("LEVEL")
+                        .takes_value(true)
+                        .default_value("30")
+                        .help("First cooler level %")
+                    ).arg(Arg::with_name("max")
+                        .long("max")
+                        .value_name("LEVEL")
+                        .takes_value(true)
+                        .default_value("100")
+                        .help("Last cooler level %")
+                    ).arg(Arg::with_name("step")
+                        .long("step")
+                        .value_name("LEVEL")
+                        .takes_value(true)
+                        .default_value("10")
+                        .help("Cooler level % increment")
+                    ).arg(Arg::with_name("settle")
+                        .long("settle")
+                        .value_name("TIME")
+                        .takes_value(true)
+                        .default_value("10s")
+                        .help("Longest time to wait for the fan speed to settle at each step")
+                    )
                 )
             ).subcommand(SubCommand::with_name("vfp")
                 .about("GPU Boost 3.0 voltage-frequency curve")
                 .subcommand(SubCommand::with_name("export")
-                    .about("Export current curve as CSV")
-                    .arg(Arg::with_name("tabs")
+                    .about("Export current curve as CSV or JSON")
+                    .arg(Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .enum_values::<VfpFormat>()
+                        .default_value(VfpFormat::Csv.to_str())
+                        .enum_help::<VfpFormat>("File format, inferred from a .csv, .tsv or .json extension when not given")
+                    ).arg(Arg::with_name("tabs")
                         .short("t")
                         .long("tabs")
-                        .help("Separate columns using tabs")
+                        .help("Separate columns using tabs, the default for a .tsv file")
+                    ).arg(Arg::with_name("delimiter")
+                        .short("d")
+                        .long("delimiter")
+                        .value_name("CHAR")
+                        .takes_value(true)
+                        .conflicts_with("tabs")
+                        .help("Separate columns using the given character")
+                    ).arg(Arg::with_name("no_header")
+                        .long("no-header")
+                        .help("CSV has no header row, columns are voltage (uV), frequency (kHz), offset (kHz)")
                     ).arg(Arg::with_name("output")
                         .value_name("OUTPUT")
                         .takes_value(true)
                         .default_value("-")
-                        .help("Output file path")
+                        .help("Output file path, {gpu} is replaced by the GPU index when several are selected")
                     )
                 ).subcommand(SubCommand::with_name("import")
-                    .about("Import a modified curve from CSV")
-                    .arg(Arg::with_name("tabs")
+                    .about("Import a modified curve from CSV or JSON")
+                    .arg(Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .enum_values::<VfpFormat>()
+                        .default_value(VfpFormat::Csv.to_str())
+                        .enum_help::<VfpFormat>("File format, inferred from a .csv, .tsv or .json extension when not given")
+                    ).arg(Arg::with_name("tabs")
                         .short("t")
                         .long("tabs")
-                        .help("Separate columns using tabs")
+                        .help("Separate columns using tabs, the default for a .tsv file")
+                    ).arg(Arg::with_name("delimiter")
+                        .short("d")
+                        .long("delimiter")
+                        .value_name("CHAR")
+                        .takes_value(true)
+                        .conflicts_with("tabs")
+                        .help("Separate columns using the given character")
+                    ).arg(Arg::with_name("no_header")
+                        .long("no-header")
+                        .help("CSV has no header row, columns are voltage (uV), frequency (kHz), offset (kHz)")
                     ).arg(Arg::with_name("input")
                         .value_name("INPUT")
                         .takes_value(true)
                         .default_value("-")
                         .help("Input file path")
+                    ).arg(Arg::with_name("dry_run")
+                        .short("n")
+                        .long("dry-run")
+                        .help("Show the offsets that would be applied without applying them")
+                    ).arg(Arg::with_name("interpolate")
+                        .short("i")
+                        .long("interpolate")
+                        .help("Fill in offsets for points between those in the input")
+                    ).arg(Arg::with_name("monotonic")
+                        .short("m")
+                        .long("enforce-monotonic")
+                        .help("Lower points that would run faster than a higher voltage point")
+                    ).arg(Arg::with_name("strict")
+                        .long("strict")
+                        .requires("monotonic")
+                        .help("Fail instead of lowering points")
+                    ).arg(Arg::with_name("from_voltage")
+                        .long("from-voltage")
+                        .value_name("UV")
+                        .takes_value(true)
+                        .help("Ignore rows below this voltage (uV)")
+                    ).arg(Arg::with_name("to_voltage")
+                        .long("to-voltage")
+                        .value_name("UV")
+                        .takes_value(true)
+                        .help("Ignore rows above this voltage (uV)")
+                    ).arg(Arg::with_name("and_test")
+                        .long("and-test")
+                        .value_name("COMMAND")
+                        .takes_value(true)
+                        .allow_hyphen_values(true)
+                        .conflicts_with("dry_run")
+                        .help("Run this stress test once the curve is applied and report the result, its output goes to stderr with -O json")
+                    ).arg(Arg::with_name("revert")
+                        .long("revert-on-fail")
+                        .requires("and_test")
+                        .help("Restore the previous curve if the test fails")
+                    ).arg(Arg::with_name("sample_csv")
+                        .long("sample-to-csv")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .requires("and_test")
+                        .help("Record temperature, clock, power and voltage samples taken during the test, {gpu} is replaced by the GPU index when several are selected")
+                    )
+                ).subcommand(SubCommand::with_name("verify")
+                    .about("Check that the applied curve matches a CSV or JSON file")
+                    .arg(Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .enum_values::<VfpFormat>()
+                        .default_value(VfpFormat::Csv.to_str())
+                        .enum_help::<VfpFormat>("File format, inferred from a .csv, .tsv or .json extension when not given")
+                    ).arg(Arg::with_name("tabs")
+                        .short("t")
+                        .long("tabs")
+                        .help("Separate columns using tabs, the default for a .tsv file")
+                    ).arg(Arg::with_name("delimiter")
+                        .short("d")
+                        .long("delimiter")
+                        .value_name("CHAR")
+                        .takes_value(true)
+                        .conflicts_with("tabs")
+                        .help("Separate columns using the given character")
+                    ).arg(Arg::with_name("no_header")
+                        .long("no-header")
+                        .help("CSV has no header row, columns are voltage (uV), frequency (kHz), offset (kHz)")
+                    ).arg(Arg::with_name("tolerance")
+                        .value_name("TOLERANCE")
+                        .short("T")
+                        .long("tolerance")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Allowed difference between offsets (MHz)")
+                    ).arg(Arg::with_name("input")
+                        .value_name("INPUT")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Input file path")
+                    )
+                ).subcommand(SubCommand::with_name("snapshot")
+                    .about("Save the live curve offsets as a known good tune to restore later")
+                    .arg(Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .enum_values::<VfpFormat>()
+                        .default_value(VfpFormat::Json.to_str())
+                        .enum_help::<VfpFormat>("File format, inferred from a .csv, .tsv or .json extension when not given")
+                    ).arg(Arg::with_name("output")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Snapshot file path, {gpu} is replaced by the GPU index when several are selected")
+                    )
+                ).subcommand(SubCommand::with_name("restore")
+                    .about("Apply a curve snapshot and verify that each point took")
+                    .arg(Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .enum_values::<VfpFormat>()
+                        .default_value(VfpFormat::Json.to_str())
+                        .enum_help::<VfpFormat>("File format, inferred from a .csv, .tsv or .json extension when not given")
+                    ).arg(Arg::with_name("tolerance")
+                        .value_name("TOLERANCE")
+                        .short("T")
+                        .long("tolerance")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Allowed difference between offsets (MHz)")
+                    ).arg(Arg::with_name("input")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Snapshot file path, as given to vfp snapshot")
                     )
                 ).subcommand(SubCommand::with_name("lock")
                     .about("Lock the clock to a specific point on the curve")
                     .arg(Arg::with_name("point")
                         .value_name("POINT")
                         .takes_value(true)
-                        .required(true)
+                        .required_unless("mode")
                         .help("Point index to lock at")
                     ).arg(Arg::with_name("voltage")
                         .short("v")
                         .long("voltage")
                         .help("Interpret point as voltage instead of index")
+                    ).arg(Arg::with_name("nearest_voltage")
+                        .short("n")
+                        .long("nearest-voltage")
+                        .requires("voltage")
+                        .help("Snap the voltage to the closest point on the curve")
+                    ).arg(Arg::with_name("mode")
+                        .long("mode")
+                        .value_name("MODE")
+                        .takes_value(true)
+                        .enum_values::<ClockLockMode>()
+                        .enum_help::<ClockLockMode>("Lock mode, none removes the lock like vfp unlock [default: manual]")
+                    ).arg(Arg::with_name("min_voltage")
+                        .long("min-voltage")
+                        .value_name("UV")
+                        .takes_value(true)
+                        .help("Refuse to lock below this voltage (uV)")
+                    ).arg(Arg::with_name("max_voltage")
+                        .long("max-voltage")
+                        .value_name("UV")
+                        .takes_value(true)
+                        .help("Refuse to lock above this voltage (uV)")
                     )
+                ).subcommand(SubCommand::with_name("locks")
+                    .about("List active locks without changing them")
                 ).subcommand(SubCommand::with_name("unlock")
                     .about("Remove any existing locks")
+                    .arg(Arg::with_name("list")
+                        .short("l")
+                        .long("list")
+                        .help("Show the locks being removed first")
+                    )
                 ).subcommand(SubCommand::with_name("auto")
                     .about("Run a series of automated tests to determine optimal clocks")
                     .arg(Arg::with_name("fan")
@@ -276,14 +1215,14 @@ fn main_result() -> Result<i32, Error> {
                         .long("step")
                         .takes_value(true)
                         .default_value("16")
-                        .help("Testing step resolution (MHz)")
+                        .help("Testing step resolution (MHz, or % of boost clock)")
                     ).arg(Arg::with_name("max")
                         .value_name("MAX")
                         .short("M")
                         .long("max")
                         .takes_value(true)
                         .default_value("2200")
-                        .help("Testing max frequency (MHz)")
+                        .help("Testing max frequency (MHz, or % of boost clock)")
                     ).arg(Arg::with_name("start")
                         .value_name("START")
                         .short("s")
@@ -297,12 +1236,106 @@ fn main_result() -> Result<i32, Error> {
                         .long("end")
                         .takes_value(true)
                         .help("Point index to end at")
+                    ).arg(Arg::with_name("voltage_min")
+                        .value_name("UV")
+                        .long("voltage-min")
+                        .takes_value(true)
+                        .help("Skip points below this voltage (uV)")
+                    ).arg(Arg::with_name("voltage_max")
+                        .value_name("UV")
+                        .long("voltage-max")
+                        .takes_value(true)
+                        .help("Skip points above this voltage (uV)")
                     ).arg(Arg::with_name("test")
                         .value_name("TEST")
                         .short("t")
                         .long("test")
                         .takes_value(true)
                         .help("Testing binary to use (see `help auto test`)")
+                    ).arg(Arg::with_name("test_args")
+                        .value_name("ARGS")
+                        .long("test-args")
+                        .takes_value(true)
+                        .allow_hyphen_values(true)
+                        .requires("test")
+                        .help("Arguments passed to the testing binary, separated by spaces and quoted like a shell would")
+                    ).arg(Arg::with_name("test_env")
+                        .value_name("KEY=VALUE")
+                        .long("test-env")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .requires("test")
+                        .help("Set an environment variable for the testing binary")
+                    ).arg(Arg::with_name("pass_codes")
+                        .long("pass-codes")
+                        .value_name("CODES")
+                        .takes_value(true)
+                        .requires("test")
+                        .help("Comma separated exit codes meaning the test passed [default: 0]")
+                    ).arg(Arg::with_name("fail_codes")
+                        .long("fail-codes")
+                        .value_name("CODES")
+                        .takes_value(true)
+                        .requires("test")
+                        .help("Exit codes meaning the test failed, when given any unlisted code stops the sweep")
+                    ).arg(Arg::with_name("retry_codes")
+                        .long("retry-codes")
+                        .value_name("CODES")
+                        .takes_value(true)
+                        .requires("test")
+                        .help("Exit codes meaning the test should be run again")
+                    ).arg(Arg::with_name("abort_codes")
+                        .long("abort-codes")
+                        .value_name("CODES")
+                        .takes_value(true)
+                        .requires("test")
+                        .help("Exit codes meaning the sweep should stop")
+                    ).arg(Arg::with_name("events_json")
+                        .long("events-json")
+                        .help("Report progress as one JSON event per line on stderr")
+                    ).arg(Arg::with_name("settle_tolerance")
+                        .value_name("MHZ")
+                        .long("settle-tolerance")
+                        .takes_value(true)
+                        .default_value("5")
+                        .help("Clock variation allowed between reads before a test starts (MHz)")
+                    ).arg(Arg::with_name("settle_max")
+                        .value_name("MS")
+                        .long("settle-max")
+                        .takes_value(true)
+                        .default_value("2000")
+                        .help("Longest time to wait for the clock to settle (ms)")
+                    ).arg(Arg::with_name("strategy")
+                        .value_name("STRATEGY")
+                        .long("strategy")
+                        .takes_value(true)
+                        .enum_values::<AutoStrategy>()
+                        .default_value(AutoStrategy::Down.to_str())
+                        .enum_help::<AutoStrategy>("How to search for the highest stable clock of each point")
+                    ).arg(Arg::with_name("detect_reset")
+                        .value_name("ENABLE")
+                        .long("detect-reset")
+                        .takes_value(true)
+                        .possible_values(POSSIBLE_BOOL)
+                        .default_value(POSSIBLE_BOOL_ON)
+                        .help("Count a test as failed if the driver reset the GPU during it, even if the test passed")
+                    ).arg(Arg::with_name("baseline")
+                        .long("baseline")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Compare results against the curve in this CSV or JSON file, saving the current curve there first if it doesn't exist")
+                    ).arg(Arg::with_name("apply")
+                        .long("apply")
+                        .help("Apply the discovered curve once testing completes")
+                    ).arg(Arg::with_name("monotonic")
+                        .long("enforce-monotonic")
+                        .requires("apply")
+                        .help("Lower points that would run faster than a higher voltage point")
+                    ).arg(Arg::with_name("strict")
+                        .long("strict")
+                        .requires("monotonic")
+                        .help("Fail instead of lowering points")
                     ).subcommand(SubCommand::with_name("test")
                         .about("Runs a single test cycle, monitoring the GPU and waiting for a stress test to run. Do not use this command directly.")
                         .arg(Arg::with_name("voltage")
@@ -326,91 +1359,235 @@ fn main_result() -> Result<i32, Error> {
                     .help("Voltage")
                 )
             )
-        ).setting(AppSettings::SubcommandRequiredElseHelp);
-
-    let matches = app.get_matches();
+        ).subcommand(SubCommand::with_name("batch")
+            .about("Run commands from a file, one per line with shell-style quoting, stopping at the first failure without undoing earlier lines")
+            .arg(Arg::with_name("input")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+                .help("Command file path, or - for stdin")
+            )
+        ).subcommand(SubCommand::with_name("guard")
+            .about("Apply a batch profile, then reset all settings if the GPU stops responding. A hard hang may still need a reboot")
+            .arg(Arg::with_name("profile")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+                .help("Batch file of commands to apply, or - for stdin")
+            ).arg(Arg::with_name("interval")
+                .long("interval")
+                .value_name("TIME")
+                .takes_value(true)
+                .default_value("1s")
+                .help("How often to check the GPU")
+            ).arg(Arg::with_name("hang_timeout")
+                .long("hang-timeout")
+                .value_name("TIME")
+                .takes_value(true)
+                .default_value("10s")
+                .help("How long the GPU may take to answer before it is considered hung")
+            )
+        ).subcommand(SubCommand::with_name("doctor")
+            .about("Check the driver, GPUs and write access, and list the features each GPU supports")
+        ).subcommand(SubCommand::with_name("apply-env")
+            .about("Apply settings from NVOCLOCK_VOLTAGE_BOOST, NVOCLOCK_POWER_LIMIT, NVOCLOCK_THERMAL_LIMIT, NVOCLOCK_PSTATE_OFFSET and NVOCLOCK_COOLER")
+        ).setting(AppSettings::SubcommandRequiredElseHelp)
+}
 
-    let mut exit_code = 0;
+fn main_result() -> Result<i32, Error> {
+    if let Err(e) = env_logger::init() {
+        let _ = writeln!(io::stderr(), "Failed to initialize env_logger: {}", e);
+    }
 
-    nvapi::initialize()?;
+    let matches = app().get_matches();
+    error::set_debug(matches.is_present("debug"));
+
+    let init = nvapi::initialize();
+    let output = matches.value_of("out").unwrap();
+    let stdout = io::stdout();
+    let mut out: Box<dyn Write> = if is_std(output) {
+        Box::new(stdout.lock())
+    } else {
+        Box::new(fs::File::create(output)?)
+    };
+
+    // doctor reports initialization failures itself
+    if matches.subcommand_name() == Some("doctor") {
+        return doctor(&matches, init, &mut *out)
+    }
 
-    let driver_version = nvapi::driver_version()?;
-    info!("Driver version: {} ({})", driver_version.1, driver_version.0);
-    info!("Interface version: {}", nvapi::interface_version()?);
+    match init {
+        Err(e @ Status::LibraryNotFound) | Err(e @ Status::NvidiaDeviceNotFound) => return Err(Error::NoDriver(e)),
+        res => res?,
+    }
 
-    let gpu = matches.values_of("gpu");
+    run(&matches, &mut *out)
+}
+
+/// GPUs are selected by index, or by name as printed by `list`.
+///
+/// A name that matches exactly takes precedence, otherwise any GPU whose name contains it is selected.
+fn gpu_indices(gpus: &[Gpu], gpu: Option<clap::Values>) -> Result<Vec<usize>, Error> {
+    let v = match gpu {
+        Some(gpu) => {
+            let mut v = Vec::new();
+            let mut names = None;
+            for selector in gpu {
+                if let Ok(index) = usize::from_str(selector) {
+                    v.extend((0..gpus.len()).filter(|&i| i == index));
+                    continue
+                }
 
-    fn single_gpu<'a>(gpus: &[&'a Gpu]) -> Result<&'a Gpu, Error> {
-        let mut gpus = gpus.into_iter();
-        gpus.next().ok_or_else(|| Error::from("no GPU selected"))
-            .and_then(|g| match gpus.next() {
-                None => Ok(*g),
-                Some(..) => Err(Error::from("multiple GPUs selected")),
-            })
+                if names.is_none() {
+                    names = Some(gpus.iter().map(|gpu| gpu.inner().full_name()).collect::<Result<Vec<_>, _>>()?);
+                }
+                let names = names.as_ref().unwrap();
+
+                let exact = (0..gpus.len()).filter(|&i| names[i] == selector).collect::<Vec<_>>();
+                if exact.is_empty() {
+                    let selector = selector.to_lowercase();
+                    v.extend((0..gpus.len()).filter(|&i| names[i].to_lowercase().contains(&selector)));
+                } else {
+                    v.extend(exact);
+                }
+            }
+
+            v.sort();
+            v.dedup();
+            v
+        },
+        None => (0..gpus.len()).collect(),
+    };
+
+    if v.is_empty() {
+        Err(Error::from(Status::NvidiaDeviceNotFound))
+    } else {
+        Ok(v)
     }
+}
 
-    fn select_gpus<'a>(gpus: &'a [Gpu], gpu: Option<clap::Values>) -> Result<Vec<&'a Gpu>, Error> {
-        let v = match gpu {
-            Some(gpu) => {
-                let gpu = gpu.map(usize::from_str).collect::<Result<Vec<_>, _>>()?;
+fn doctor(matches: &ArgMatches, init: nvapi::Result<()>, out: &mut dyn Write) -> Result<i32, Error> {
+    let oformat = output_format(matches)?;
+    let checks = doctor::diagnose(init, matches.values_of("gpu"));
 
-                gpus.iter().enumerate().filter_map(|(i, g)| {
-                    for &gpu in &gpu {
-                        if i == gpu {
-                            return Some(g)
-                        }
-                    }
+    match oformat {
+        OutputFormat::Human => human::print_doctor(out, checks.iter())?,
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &checks)?
+        },
+    }
 
-                    None
-                }).collect::<Vec<_>>()
-            },
-            None => gpus.into_iter().collect(),
-        };
+    Ok(if checks.iter().any(|c| c.result == DoctorResult::Fail) {
+        error::EXIT_FAILURE
+    } else {
+        0
+    })
+}
 
-        if v.is_empty() {
-            Err(Error::from(Status::NvidiaDeviceNotFound))
-        } else {
-            Ok(v)
+fn run(matches: &ArgMatches, out: &mut dyn Write) -> Result<i32, Error> {
+    let mut exit_code = 0;
+
+    let driver_version = nvapi::driver_version()?;
+    let interface_version = nvapi::interface_version()?;
+    info!("Driver version: {} ({})", driver_version.1, driver_version.0);
+    info!("Interface version: {}", interface_version);
+
+    let gpu = matches.values_of("gpu");
+    let tty = is_std(matches.value_of("out").unwrap()) && io::stdout().is_terminal();
+
+    /// The only selected GPU, `indices` are the enumeration indices of `gpus` for the error message
+    fn single_gpu<'a>(gpus: &[&'a Gpu], indices: &[usize]) -> Result<&'a Gpu, Error> {
+        match gpus.len() {
+            0 => Err(Error::from("no GPU selected")),
+            1 => Ok(gpus[0]),
+            _ => {
+                let selected = gpus.iter().zip(indices).map(|(gpu, i)| format!("{} ({})", i,
+                    gpu.inner().full_name().unwrap_or_else(|_| "unknown".into())
+                )).collect::<Vec<_>>();
+                Err(Error::from(format!("this command needs a single GPU but {} were selected: {}; pick one with -g",
+                    gpus.len(), selected.join(", ")
+                )))
+            },
         }
     }
 
-    let oformat = matches.value_of("oformat").map(OutputFormat::from_str).unwrap()?;
+    let keyed = matches.is_present("json_keyed");
+    let clock_unit = matches.value_of("clock_unit").map(ClockUnit::from_str).unwrap()?;
+
+    let oformat = output_format(matches)?;
+
+    if writes(matches) {
+        privilege::require()?;
+    }
 
     match matches.subcommand() {
-        ("list", Some(..)) => {
+        ("list", Some(matches)) => {
+            let verbose = matches.is_present("verbose");
             let gpus = Gpu::enumerate()?
                 .into_iter()
-                .map(|gpu| Ok::<_, Status>(GpuDescriptor {
-                    name: gpu.inner().full_name()?,
+                .map(|gpu| Ok::<_, Status>(if verbose {
+                    let info = gpu.info()?;
+                    GpuDescriptor {
+                        name: info.name,
+                        gpu_id: Some(gpu.inner().gpu_id()?),
+                        pci: Some(info.pci),
+                        memory: Some(info.memory.dedicated),
+                        bios_version: Some(info.bios_version),
+                    }
+                } else {
+                    GpuDescriptor {
+                        name: gpu.inner().full_name()?,
+                        gpu_id: None,
+                        pci: None,
+                        memory: None,
+                        bios_version: None,
+                    }
                 })).collect::<Result<Vec<_>, _>>()?;
 
             match oformat {
                 OutputFormat::Human => for (i, gpu) in gpus.into_iter().enumerate() {
-                    println!("GPU #{}: {}", i, gpu.name);
+                    writeln!(out, "GPU #{}: {}", i, gpu.name)?;
+                    if let (Some(id), Some(pci), Some(memory), Some(bios)) = (gpu.gpu_id, gpu.pci, gpu.memory, gpu.bios_version) {
+                        writeln!(out, "    ID {:#x}, PCI {}, {:.2} VRAM, VBIOS {}", id, pci, memory, bios)?;
+                    }
+                },
+                OutputFormat::Json => {
+                    let indices = (0..gpus.len()).collect::<Vec<_>>();
+                    serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&gpus, clock_unit)?, &indices, keyed))?
                 },
+            }
+        },
+        ("info", Some(matches)) if matches.is_present("driver") => {
+            let driver = DriverDescriptor {
+                version: driver_version.0,
+                branch: driver_version.1,
+                interface: interface_version,
+            };
+
+            match oformat {
+                OutputFormat::Human => human::print_driver(out, &driver)?,
                 OutputFormat::Json => {
-                    serde_json::to_writer_pretty(io::stdout(), &gpus)?
+                    serde_json::to_writer_pretty(&mut *out, &driver)?
                 },
             }
         },
         ("info", Some(matches)) => {
             let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            let indices = gpu_indices(&gpus, gpu)?;
+            let gpus = indices.iter().map(|&i| &gpus[i]).collect::<Vec<_>>();
 
             match oformat {
                 OutputFormat::Human => {
 
                     for gpu in gpus {
                         let info = gpu.info()?;
-                        human::print_info(&info);
-                        println!();
+                        human::print_info(out, &info)?;
+                        writeln!(out)?;
                     }
                 },
                 OutputFormat::Json => {
-                    serde_json::to_writer_pretty(
-                        io::stdout(),
-                        &gpus.into_iter().map(|gpu| gpu.info()).collect::<Result<Vec<_>, _>>()?
-                    )?;
+                    let info = gpus.into_iter().map(|gpu| gpu.info()).collect::<Result<Vec<_>, _>>()?;
+                    serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&info, clock_unit)?, &indices, keyed))?;
                 },
             }
         },
@@ -418,9 +1595,40 @@ fn main_result() -> Result<i32, Error> {
             const NANOS_IN_SECOND: f64 = 1e9;
 
             let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            let indices = gpu_indices(&gpus, gpu)?;
+            let gpus = indices.iter().map(|&i| &gpus[i]).collect::<Vec<_>>();
             let monitor = matches.value_of("monitor").map(f64::from_str).invert()?
                 .map(|v| Duration::new(v as u64, (v.fract() * NANOS_IN_SECOND) as u32));
+            let retries = match matches.value_of("retries") {
+                Some(retries) => u32::from_str(retries)?,
+                // a one-shot status should fail fast, a monitor shouldn't stop on one bad sample
+                None if monitor.is_some() => 3,
+                None => 0,
+            };
+            let clocks = match matches.values_of("clock") {
+                Some(clocks) => clocks.map(ClockDomain::from_str).collect::<Result<Vec<_>, _>>()?,
+                None => ClockDomain::values().collect(),
+            };
+
+            let duration = matches.value_of("duration").map(parse_duration).invert()?;
+            let start = Instant::now();
+
+            let show_delta = matches.is_present("delta");
+            let summarize = matches.is_present("summarize");
+            let thresholds = TempThresholds {
+                warn: matches.value_of("warn_temp").map(i32::from_str).invert()?.map(Celsius),
+                critical: matches.value_of("crit_temp").map(i32::from_str).invert()?.map(Celsius),
+            };
+            let mut previous = gpus.iter().map(|_| None).collect::<Vec<Option<GpuStatus>>>();
+            let sparklines = matches.is_present("sparklines");
+            let fail_temp = matches.value_of("fail_over_temp").map(i32::from_str).invert()?.map(Celsius);
+            let fail_power = matches.value_of("fail_over_power").map(u32::from_str).invert()?.map(Percentage);
+            let over_limits = |status: &GpuStatus| {
+                let hot = fail_temp.is_some_and(|limit| status.sensors.iter().any(|&(_, temp)| temp > limit));
+                let power = fail_power.is_some_and(|limit| status.power.iter().any(|&power| power > limit));
+                hot || power
+            };
+            let mut history = gpus.iter().map(|_| human::History::default()).collect::<Vec<_>>();
 
             loop {
                 match oformat {
@@ -432,7 +1640,7 @@ fn main_result() -> Result<i32, Error> {
                         let show_vfp = parse_bool_match(&matches, "vfp");
                         let show_pstates = parse_bool_match(&matches, "pstates");
 
-                        for &gpu in &gpus {
+                        for (i, &gpu) in gpus.iter().enumerate() {
                             let mut info = None;
                             let mut set = None;
 
@@ -452,276 +1660,958 @@ fn main_result() -> Result<i32, Error> {
                                 Ok(set.get_or_insert(gpu.settings()?))
                             }
 
-                            let status = gpu.status()?;
+                            let status = status_retry(gpu, retries)?;
+                            if over_limits(&status) {
+                                exit_code = error::EXIT_FAILURE;
+                            }
 
                             if show_status {
-                                human::print_status(&status);
+                                human::print_status(out, &status, if show_delta { previous[i].as_ref() } else { None })?;
 
-                                human::print_settings(requires_set(gpu, &mut set)?);
+                                human::print_settings(out, requires_set(gpu, &mut set)?, None)?;
 
-                                println!();
+                                if sparklines {
+                                    history[i].push(&status);
+                                    human::print_history(out, &history[i], !tty)?;
+                                }
+
+                                writeln!(out)?;
                             }
 
                             if show_clocks {
                                 let info = requires_info(gpu, &mut info)?;
-                                human::print_clocks(&info.base_clocks, &info.boost_clocks, &status.clocks, &status.utilization);
+                                human::print_clocks(out, &clocks, &info.base_clocks, &info.boost_clocks, &status.clocks, &status.utilization)?;
                             }
 
                             if show_sensors {
                                 let info = requires_info(gpu, &mut info)?;
                                 let set = requires_set(gpu, &mut set)?;
 
-                                human::print_sensors(status.sensors.iter()
+                                human::print_sensors(out, status.sensors.iter()
                                     .zip(info.sensor_limits.iter().zip(set.sensor_limits.iter().cloned())
                                         .map(Some).chain(iter::repeat(None))
-                                    ).map(|(&(ref desc, temp), limit)| (desc, limit, temp))
-                                );
+                                    ).map(|(&(ref desc, temp), limit)| (desc, limit, temp)),
+                                    &thresholds
+                                )?;
                             }
 
                             if show_coolers {
-                                human::print_coolers(
+                                human::print_coolers(out,
                                     status.coolers.iter().map(|&(ref desc, ref cooler)| (desc, cooler)),
                                     status.tachometer
-                                );
+                                )?;
                             }
 
                             if show_vfp {
                                 let set = requires_set(gpu, &mut set)?;
 
-                                let vfp = status.vfp.as_ref().ok_or(Status::NotSupported)?;
-                                let vfp_deltas = set.vfp.as_ref().ok_or(Status::NotSupported)?;
-                                let lock = set.vfp_locks.iter().map(|(_, e)| e)
-                                    .filter(|&e| e.mode == ClockLockMode::Manual).map(|e| e.voltage).max();
-                                human::print_vfp(vfp.graphics.iter().zip(vfp_deltas.graphics.iter())
-                                    .map(|((i0, p), (i1, d))| {
-                                        assert_eq!(i0, i1);
-                                        (*i0, VfPoint::new(p.clone(), d.clone()))
-                                    }),
-                                    lock, status.voltage
-                                );
+                                if let (Some(vfp), Some(vfp_deltas)) = (status.vfp.as_ref(), set.vfp.as_ref()) {
+                                    let lock = set.vfp_locks.values()
+                                        .filter(|&e| e.mode == ClockLockMode::Manual).map(|e| e.voltage).max();
+                                    human::print_vfp(out, vfp.graphics.iter().zip(vfp_deltas.graphics.iter())
+                                        .map(|((i0, p), (i1, d))| {
+                                            assert_eq!(i0, i1);
+                                            (*i0, VfPoint::new(p.clone(), *d))
+                                        }),
+                                        lock, status.voltage
+                                    )?;
+                                } else {
+                                    writeln!(out, "VFP not supported on this GPU")?;
+                                }
                             }
 
                             if show_pstates {
                                 let info = requires_info(gpu, &mut info)?;
                                 let set = requires_set(gpu, &mut set)?;
 
-                                human::print_pstates(info.pstate_limits.iter()
+                                human::print_pstates(out, info.pstate_limits.iter()
                                     .flat_map(|(&p, e)| e.iter().map(move |(&c, e)|
                                         (p, c, e,
                                             set.pstate_deltas.get(&p).and_then(|p| p.get(&c).cloned())
                                         )
                                     )),
                                     Some(status.pstate)
-                                );
+                                )?;
                             }
 
-                            println!();
+                            previous[i] = Some(status);
+
+                            writeln!(out)?;
                         }
                     },
                     OutputFormat::Json => {
-                        let status = &gpus.iter().map(|&gpu| gpu.status()).collect::<Result<Vec<_>, _>>()?;
+                        let status = &gpus.iter().map(|&gpu| status_retry(gpu, retries).map(|mut status| {
+                            status.clocks = status.clocks.into_iter().filter(|&(c, _)| clocks.contains(&c)).collect();
+                            status
+                        })).collect::<Result<Vec<_>, _>>()?;
+                        if status.iter().any(&over_limits) {
+                            exit_code = error::EXIT_FAILURE;
+                        }
+                        let mut status = clock_json(status, clock_unit)?;
+                        if summarize {
+                            if let serde_json::Value::Array(ref mut status) = status {
+                                for status in status.iter_mut().filter_map(|s| s.as_object_mut()) {
+                                    for field in SUMMARY_OMITS {
+                                        status.remove(*field);
+                                    }
+                                }
+                            }
+                        }
+                        let status = gpu_json(status, &indices, keyed);
                         if monitor.is_some() {
                             // in monitor mode, newlines separate statuses so can't be pretty
-                            serde_json::to_writer(io::stdout(), status)?;
-                            println!();
+                            serde_json::to_writer(&mut *out, &status)?;
+                            writeln!(out)?;
                         } else {
-                            serde_json::to_writer_pretty(io::stdout(), status)?;
+                            serde_json::to_writer_pretty(&mut *out, &status)?;
                         }
                     },
                 }
 
                 if let Some(monitor) = monitor.clone() {
+                    if duration.map(|d| start.elapsed() + monitor > d).unwrap_or(false) {
+                        break
+                    }
                     sleep(monitor)
                 } else {
                     break
                 }
             }
         },
-        ("get", Some(..)) => {
+        ("headroom", Some(..)) => {
+            let gpus = Gpu::enumerate()?;
+            let indices = gpu_indices(&gpus, gpu)?;
+            let gpus = indices.iter().map(|&i| &gpus[i]).collect::<Vec<_>>();
+
+            let reports = gpus.into_iter().map(|gpu| {
+                let info = gpu.info()?;
+                let status = gpu.status()?;
+                let settings = gpu.settings()?;
+
+                Ok(HeadroomReport {
+                    constraints: headroom::headroom(&info, &status, &settings),
+                    name: info.name,
+                })
+            }).collect::<Result<Vec<_>, Error>>()?;
+
+            match oformat {
+                OutputFormat::Human => for report in &reports {
+                    human::print_headroom(out, report)?;
+                },
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&reports, clock_unit)?, &indices, keyed))?
+                },
+            }
+        },
+        ("serve", Some(matches)) => serve::serve(matches.value_of("listen").unwrap(), gpu, keyed, clock_unit)?,
+        ("dump", Some(..)) => {
+            let gpus = Gpu::enumerate()?;
+            let indices = gpu_indices(&gpus, gpu)?;
+
+            let dumps = indices.iter().map(|&i| Ok(GpuDump {
+                info: gpus[i].info()?,
+                status: gpus[i].status()?,
+                settings: gpus[i].settings()?,
+            })).collect::<Result<Vec<_>, Error>>()?;
+
+            // always JSON, human output would just be info, status and get in a row
+            serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&dumps, clock_unit)?, &indices, keyed))?
+        },
+        ("schema", Some(matches)) => {
+            let kind = matches.value_of("kind").map(SchemaKind::from_str).unwrap()?;
+
+            serde_json::to_writer_pretty(&mut *out, &schema::schema(kind, keyed, clock_unit))?
+        },
+        ("get", Some(matches)) if matches.is_present("effective") => {
+            let gpus = Gpu::enumerate()?;
+            let indices = gpu_indices(&gpus, gpu)?;
+            let pstates = pstate_filter(matches)?;
+
+            let clocks = indices.iter().map(|&i| {
+                let info = gpus[i].info()?;
+                let settings = gpus[i].settings()?;
+
+                Ok(info.pstate_limits.iter()
+                    .filter(|&(pstate, _)| pstates.as_ref().is_none_or(|p| p.contains(pstate)))
+                    .flat_map(|(&pstate, clocks)| clocks.iter().map(move |(&clock, limit)| (pstate, clock, limit)))
+                    .map(|(pstate, clock, limit)| {
+                        let delta = settings.pstate_deltas.get(&pstate)
+                            .and_then(|p| p.get(&clock).cloned()).unwrap_or_default();
+                        EffectiveClock {
+                            pstate,
+                            clock,
+                            base: limit.frequency.max,
+                            delta,
+                            effective: limit.frequency.max + delta,
+                        }
+                    }).collect::<Vec<_>>())
+            }).collect::<Result<Vec<_>, Error>>()?;
+
+            match oformat {
+                OutputFormat::Human => for clocks in &clocks {
+                    human::print_effective_clocks(out, clocks.iter())?;
+                },
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&clocks, clock_unit)?, &indices, keyed))?
+                },
+            }
+        },
+        ("get", Some(matches)) if matches.is_present("only") => {
             let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            let indices = gpu_indices(&gpus, gpu)?;
+            let gpus = indices.iter().map(|&i| &gpus[i]).collect::<Vec<_>>();
+            let field = matches.value_of("only").map(SettingField::from_str).unwrap()?;
+
+            let mut values = Vec::new();
+            for gpu in gpus {
+                let set = gpu.settings()?;
+                values.push(match field {
+                    SettingField::VoltageBoost => set.voltage_boost.iter().map(|v| (v.to_string(), json!(v))).collect::<Vec<_>>(),
+                    SettingField::ThermalLimit => set.sensor_limits.iter().map(|v| (v.to_string(), json!(v))).collect(),
+                    SettingField::PowerLimit => set.power_limits.iter().map(|v| (v.to_string(), json!(v))).collect(),
+                    SettingField::CoolerLevel => set.coolers.iter().map(|(_, c)| (c.level.to_string(), json!(c.level))).collect(),
+                    SettingField::Overvolt => set.overvolt.iter().map(|v| (v.to_string(), json!(v))).collect(),
+                    SettingField::VfpLock => set.vfp_locks.values()
+                        .filter(|l| l.mode == ClockLockMode::Manual)
+                        .map(|l| (l.voltage.to_string(), json!(l.voltage))).collect(),
+                });
+            }
+
+            match oformat {
+                OutputFormat::Human => for (value, _) in values.into_iter().flat_map(|v| v.into_iter()) {
+                    writeln!(out, "{}", value)?;
+                },
+                OutputFormat::Json => {
+                    let values = values.into_iter().map(|mut v| match v.len() {
+                        0 => serde_json::Value::Null,
+                        1 => v.remove(0).1,
+                        _ => serde_json::Value::Array(v.into_iter().map(|(_, v)| v).collect()),
+                    }).collect::<Vec<_>>();
+                    serde_json::to_writer_pretty(&mut *out, &gpu_json(serde_json::Value::Array(values), &indices, keyed))?
+                },
+            }
+        },
+        ("get", Some(matches)) => {
+            let gpus = Gpu::enumerate()?;
+            let indices = gpu_indices(&gpus, gpu)?;
+            let gpus = indices.iter().map(|&i| &gpus[i]).collect::<Vec<_>>();
+            let pstates = pstate_filter(matches)?;
+            let tdp = matches.value_of("tdp").map(f64::from_str).invert()?;
+
+            let mut settings = gpus.into_iter().map(|gpu| gpu.settings()).collect::<Result<Vec<_>, _>>()?;
+            if let Some(ref pstates) = pstates {
+                for set in &mut settings {
+                    set.pstate_deltas = set.pstate_deltas.iter()
+                        .filter(|&(pstate, _)| pstates.contains(pstate))
+                        .map(|(&pstate, deltas)| (pstate, deltas.clone()))
+                        .collect();
+                }
+            }
 
             match oformat {
                 OutputFormat::Human => {
-                    for gpu in gpus {
-                        let set = gpu.settings()?;
-                        human::print_settings(&set);
+                    for set in &settings {
+                        human::print_settings(out, set, tdp)?;
                     }
                 },
                 OutputFormat::Json => {
-                    serde_json::to_writer_pretty(
-                        io::stdout(),
-                        &gpus.into_iter().map(|gpu| gpu.settings()).collect::<Result<Vec<_>, _>>()?
-                    )?;
+                    serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&settings, clock_unit)?, &indices, keyed))?;
+                },
+            }
+        },
+        ("reset", Some(matches)) if matches.is_present("list") => {
+            let settings = ResetSettings::possible_values_typed().iter()
+                .map(|s| SettingDescriptor {
+                    name: s.to_str(),
+                    description: s.description(),
+                }).collect::<Vec<_>>();
+
+            match oformat {
+                OutputFormat::Human => human::print_setting_descriptors(out, settings.iter())?,
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(&mut *out, &settings)?
                 },
             }
         },
         ("reset", Some(matches)) => {
             let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            let indices = gpu_indices(&gpus, gpu)?;
+            let gpus = indices.iter().map(|&i| &gpus[i]).collect::<Vec<_>>();
+
+            let only_pstates = pstate_filter(matches)?;
+            let (settings, explicit) = reset_settings(matches)?;
+
+            fn reset_result(r: nvapi::Result<()>, setting: ResetSettings, explicit: bool) -> Result<ResetEntry, Error> {
+                let (outcome, error) = match allowable_result(r) {
+                    Ok(Ok(())) => (ResetOutcome::Reset, None),
+                    Ok(Err(e)) | Err(e) if explicit => return Err((setting, e).into()),
+                    Ok(Err(e)) => (ResetOutcome::Unsupported, Some(e)),
+                    Err(e) => (ResetOutcome::Failed, Some(e)),
+                };
+
+                Ok(ResetEntry {
+                    setting: setting.to_str(),
+                    outcome,
+                    error: error.map(|e| Error::from(e).to_string()),
+                })
+            }
 
-            let (settings, explicit) = if let Some(reset) = matches.values_of("setting") {
-                (reset.map(ResetSettings::from_str).collect::<Result<_, _>>()?, true)
-            } else {
-                (ResetSettings::possible_values_typed().iter().cloned().collect::<Vec<_>>(), false)
-            };
+            if matches.is_present("dry_run") {
+                fn list<T: ToString, I: Iterator<Item=T>>(values: I) -> String {
+                    let values = values.map(|v| v.to_string()).collect::<Vec<_>>();
+                    if values.is_empty() { "none".into() } else { values.join(", ") }
+                }
+
+                let mut plans = Vec::new();
+                for gpu in gpus {
+                    let info = gpu.info()?;
+                    let set = gpu.settings()?;
+
+                    let previews = settings.iter().map(|&setting| {
+                        let (current, reset_to) = match setting {
+                            ResetSettings::VoltageBoost => (
+                                set.voltage_boost.map(|v| v.to_string()).unwrap_or_else(|| "unsupported".into()),
+                                Percentage(0).to_string(),
+                            ),
+                            ResetSettings::SensorLimits => (
+                                list(set.sensor_limits.iter()),
+                                list(info.sensor_limits.iter().map(|l| l.default)),
+                            ),
+                            ResetSettings::PowerLimits => (
+                                list(set.power_limits.iter()),
+                                list(info.power_limits.iter().map(|l| l.default)),
+                            ),
+                            ResetSettings::CoolerLevels => (
+                                list(set.coolers.iter().map(|(_, c)| format!("{} {}", c.policy, c.level))),
+                                "driver default policy".into(),
+                            ),
+                            ResetSettings::VfpDeltas => (
+                                set.vfp.as_ref().map(|vfp| format!("{} points offset",
+                                    vfp.graphics.values().filter(|&&d| d != KilohertzDelta(0)).count()
+                                )).unwrap_or_else(|| "unsupported".into()),
+                                "no curve offsets".into(),
+                            ),
+                            ResetSettings::VfpLock => (
+                                list(set.vfp_locks.values().filter(|l| l.mode == ClockLockMode::Manual).map(|l| l.voltage)),
+                                "none".into(),
+                            ),
+                            ResetSettings::PStateDeltas => (
+                                list(set.pstate_deltas.iter()
+                                    .filter(|&(pstate, _)| only_pstates.as_ref().is_none_or(|p| p.contains(pstate)))
+                                    .flat_map(|(pstate, clocks)| clocks.iter().map(move |(clock, delta)| (pstate, clock, delta)))
+                                    .filter(|&(_, _, &delta)| delta != KilohertzDelta(0))
+                                    .map(|(pstate, clock, delta)| format!("{} {} {}", pstate, clock, delta))
+                                ),
+                                "no pstate offsets".into(),
+                            ),
+                            ResetSettings::Overvolt => (
+                                list(set.overvolt.iter()),
+                                "not implemented".into(),
+                            ),
+                        };
+
+                        ResetPreview {
+                            setting: setting.to_str(),
+                            current,
+                            reset_to,
+                        }
+                    }).collect();
+
+                    plans.push(ResetPlan {
+                        name: info.name,
+                        settings: previews,
+                    });
+                }
 
-            fn warn_result(r: nvapi::Result<()>, setting: ResetSettings, explicit: bool) -> Result<(), Error> {
-                match (allowable_result(r).map_err(|e| (setting, e))?, explicit) {
-                    (Err(e), true) => Err((setting, e).into()),
-                    _ => Ok(()),
+                match oformat {
+                    OutputFormat::Human => for plan in &plans {
+                        human::print_reset_plan(out, plan)?;
+                    },
+                    OutputFormat::Json => {
+                        serde_json::to_writer_pretty(&mut *out, &gpu_json(serde_json::to_value(&plans)?, &indices, keyed))?
+                    },
                 }
+
+                return Ok(exit_code)
             }
 
+            let mut summaries = Vec::new();
             for gpu in gpus {
                 let info = gpu.info()?;
 
+                let mut entries = Vec::new();
                 for &setting in &settings {
-                    match setting {
-                        ResetSettings::VoltageBoost => warn_result(
-                            gpu.set_voltage_boost(Percentage(0)),
-                            setting, explicit
-                        )?,
-                        ResetSettings::SensorLimits => warn_result(
-                            gpu.set_sensor_limits(info.sensor_limits.iter().map(|info| info.default)),
-                            setting, explicit
-                        )?,
-                        ResetSettings::PowerLimits => warn_result(
-                            gpu.set_power_limits(info.power_limits.iter().map(|info| info.default)),
-                            setting, explicit
-                        )?,
-                        ResetSettings::CoolerLevels => warn_result(
-                            gpu.reset_cooler_levels(),
-                            setting, explicit
-                        )?,
-                        ResetSettings::VfpDeltas => warn_result(
-                            gpu.reset_vfp(), // not really necessary if we're also doing pstate reset?
-                            setting, explicit
-                        )?,
-                        ResetSettings::VfpLock => warn_result(
-                            gpu.reset_vfp_lock(),
-                            setting, explicit
-                        )?,
+                    let result = match setting {
+                        ResetSettings::VoltageBoost => gpu.set_voltage_boost(Percentage(0)),
+                        ResetSettings::SensorLimits => gpu.set_sensor_limits(info.sensor_limits.iter().map(|info| info.default)),
+                        ResetSettings::PowerLimits => gpu.set_power_limits(info.power_limits.iter().map(|info| info.default)),
+                        ResetSettings::CoolerLevels => gpu.reset_cooler_levels(),
+                        ResetSettings::VfpDeltas => gpu.reset_vfp(),
+                        ResetSettings::VfpLock => gpu.reset_vfp_lock(),
                         ResetSettings::PStateDeltas => {
-                            let pstates = info.pstate_limits.iter().flat_map(|(&pstate, l)|
-                                l.iter()
+                            let pstates = info.pstate_limits.iter()
+                                .filter(|&(pstate, _)| only_pstates.as_ref().is_none_or(|p| p.contains(pstate)))
+                                .flat_map(|(&pstate, l)| l.iter()
                                     .filter(|&(_, ref info)| info.frequency_delta.is_some())
                                     .map(move |(&clock, _)| (pstate, clock))
                             );
-                            warn_result(
-                                gpu.inner().set_pstates(pstates.map(|(pstate, clock)| (pstate, clock, KilohertzDelta(0)))),
-                                setting, explicit
-                            )?
+                            gpu.inner().set_pstates(pstates.map(|(pstate, clock)| (pstate, clock, KilohertzDelta(0))))
+                        },
+                        // TODO: reset overvolt
+                        ResetSettings::Overvolt => Err(Status::NoImplementation),
+                    };
+
+                    let entry = reset_result(result, setting, explicit)?;
+                    if let ResetOutcome::Failed = entry.outcome {
+                        error!("Reset {} failed: {}", entry.setting, entry.error.as_ref().unwrap());
+                        exit_code = error::EXIT_FAILURE;
+                    }
+                    entries.push(entry);
+                }
+
+                // the driver can accept a reset without clearing every offset, so read them back
+                let set = gpu.settings()?;
+                for entry in entries.iter_mut().filter(|e| e.outcome == ResetOutcome::Reset) {
+                    let setting = ResetSettings::from_str(entry.setting)?;
+                    match leftover_offsets(&set, setting, only_pstates.as_ref().map(|p| &p[..])) {
+                        0 => (),
+                        count => {
+                            error!("Reset {} left {} offsets applied", entry.setting, count);
+                            entry.outcome = ResetOutcome::Failed;
+                            entry.error = Some(format!("{} offsets still applied after reset", count));
+                            exit_code = error::EXIT_FAILURE;
                         },
-                        ResetSettings::Overvolt => warn_result(
-                            // TODO: reset overvolt
-                            Err(Status::NoImplementation),
-                            setting, explicit
-                        )?,
                     }
                 }
+
+                summaries.push(ResetSummary {
+                    name: info.name,
+                    settings: entries,
+                });
+            }
+
+            match oformat {
+                OutputFormat::Human => for summary in &summaries {
+                    human::print_reset(out, summary)?;
+                },
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(&mut *out, &gpu_json(serde_json::to_value(&summaries)?, &indices, keyed))?
+                },
             }
         },
         ("set", Some(matches)) => {
-            let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            // only loaded here so a broken policy file can't get in the way of reading the GPU
+            let policy = policy::Policy::load()?;
+
+            let flags = ["vboost", "tlimit", "plimit"];
+            if matches.subcommand_name().is_none() && !flags.iter().any(|&f| matches.is_present(f)) {
+                return Err(Error::Str("nothing to set, pass -V, -P or -T or a subcommand (see nvoclock set --help)"))
+            }
 
-            for gpu in &gpus {
-                if let Some(vboost) = matches.value_of("vboost").map(u32::from_str).invert()? {
-                    gpu.set_voltage_boost(Percentage(vboost))?
+            let gpus = Gpu::enumerate()?;
+            let indices = gpu_indices(&gpus, gpu)?;
+            let gpus = indices.iter().map(|&i| &gpus[i]).collect::<Vec<_>>();
+
+            for (gpu, &index) in gpus.iter().zip(&indices) {
+                let policy = policy.for_gpu(index);
+                if let Some(vboost) = matches.value_of("vboost") {
+                    let vboost = match vboost {
+                        "reset" | "default" => 0,
+                        vboost => u32::from_str(vboost)?,
+                    };
+                    gpu.set_voltage_boost(policy.voltage_boost(Percentage(vboost))?)?
                 }
 
                 if let Some(plimit) = matches.values_of("plimit") {
-                    let plimit = plimit.map(u32::from_str).map(|v| v.map(|v| Percentage(v))).collect::<Result<Vec<_>, _>>()?;
+                    let tdp = matches.value_of("tdp").map(f64::from_str).invert()?;
+                    let plimit = plimit.map(|v| parse_power_limit(v, tdp)).collect::<Result<Vec<_>, _>>()?;
+                    let info = gpu.info()?;
+                    let plimit = plimit.into_iter().zip(info.power_limits.iter()).map(|(limit, info)| {
+                        let clamped = cmp::max(info.range.min, cmp::min(info.range.max, limit));
+                        if clamped != limit {
+                            warn!("power limit {} clamped to {} (allowed {} to {})", limit, clamped, info.range.min, info.range.max);
+                        }
+                        clamped
+                    }).map(|limit| policy.power_limit(limit)).collect::<Result<Vec<_>, _>>()?;
                     gpu.set_power_limits(plimit.into_iter())?
                 }
 
                 if let Some(tlimit) = matches.values_of("tlimit") {
-                    let tlimit = tlimit.map(i32::from_str).map(|v| v.map(|v| Celsius(v))).collect::<Result<Vec<_>, _>>()?;
-                    gpu.set_sensor_limits(tlimit.into_iter())?
+                    let info = gpu.info()?;
+                    let names = info.sensors.iter().take(info.sensor_limits.len()).map(sensor_name).collect::<Vec<_>>();
+                    let mut limits = gpu.settings()?.sensor_limits;
+                    let mut position = 0;
+
+                    for limit in tlimit {
+                        let (index, value) = if let Some(split) = limit.find('=') {
+                            (sensor_index(&names, &limit[..split])?, &limit[split + 1..])
+                        } else {
+                            position += 1;
+                            (position - 1, limit)
+                        };
+
+                        let value = policy.thermal_limit(Celsius(i32::from_str(value)?))?;
+                        match limits.get_mut(index) {
+                            Some(limit) => *limit = value,
+                            None => limits.push(value),
+                        }
+                    }
+
+                    gpu.set_sensor_limits(limits.into_iter())?
                 }
             }
 
             match matches.subcommand() {
                 ("pstate", Some(matches)) => {
-                    for gpu in &gpus {
+                    let mut reports = Vec::new();
+                    for (gpu, &index) in gpus.iter().zip(&indices) {
+                        let policy = policy.for_gpu(index);
                         let pstate = matches.value_of("pstate").map(PState::from_str).unwrap()?;
                         let clock = matches.value_of("clock").map(ClockDomain::from_str).unwrap()?;
-                        let delta = matches.value_of("delta").map(i32::from_str).unwrap()?;
+                        let delta = matches.value_of("delta").map(DeltaValue::from_str).unwrap()?;
+
+                        let info = gpu.info()?;
+                        match info.pstate_limits.get(&pstate) {
+                            Some(clocks) => check_pstate_clock(clocks, pstate, clock)?,
+                            None => {
+                                let supported = info.pstate_limits.keys().map(|p| p.to_string()).collect::<Vec<_>>();
+                                return Err(Error::from(format!(
+                                    "{} is not supported by {}, expected one of: {}", pstate, info.name, supported.join(", ")
+                                )))
+                            },
+                        }
+
+                        let delta = match delta {
+                            DeltaValue::Absolute(delta) => KilohertzDelta(delta),
+                            DeltaValue::Relative(delta) => {
+                                let current = gpu.settings()?.pstate_deltas.get(&pstate)
+                                    .and_then(|p| p.get(&clock).cloned()).unwrap_or_default();
+                                let delta = current + KilohertzDelta(delta);
+
+                                match info.pstate_limits.get(&pstate).and_then(|p| p.get(&clock)).and_then(|l| l.frequency_delta) {
+                                    Some(range) => {
+                                        let clamped = cmp::max(range.min, cmp::min(range.max, delta));
+                                        if clamped != delta {
+                                            warn!("{} @ {} offset {} clamped to {}", clock, pstate, delta, clamped);
+                                        }
+                                        clamped
+                                    },
+                                    None => delta,
+                                }
+                            },
+                        };
+
+                        let delta = policy.pstate_delta(delta)?;
+                        let previous = gpu.settings()?.pstate_deltas.get(&pstate)
+                            .and_then(|p| p.get(&clock).cloned()).unwrap_or_default();
+                        gpu.inner().set_pstates([(pstate, clock, delta)].iter().cloned())?;
+
+                        let report = and_test(matches, gpu, index, gpus.len() > 1, oformat, || {
+                            gpu.inner().set_pstates([(pstate, clock, previous)].iter().cloned()).map_err(Error::from)
+                        })?;
+                        reports.extend(report.map(|report| (index, report)));
+                    }
+
+                    if reports.iter().any(|(_, report)| !report.passed) {
+                        exit_code = error::EXIT_FAILURE;
+                    }
+                    print_test_reports(out, oformat, reports, keyed, clock_unit)?;
+                },
+                ("cooler", Some(matches)) if matches.is_present("policies") => {
+                    let policies = gpus.iter().map(|gpu| {
+                        let status = gpu.status()?;
+                        Ok(status.coolers.iter().enumerate().map(|(i, (desc, cooler))| CoolerPolicies {
+                            cooler: cooler_name(desc),
+                            // a policy with a driver table is supported, the known ones are checked first to skip the query
+                            policies: CoolerPolicy::possible_values_typed().iter()
+                                .filter(|&&policy| match policy {
+                                    CoolerPolicy::None => true,
+                                    CoolerPolicy::Manual => desc.control == CoolerControl::Variable,
+                                    policy if policy == desc.default_policy || policy == cooler.policy => true,
+                                    policy => gpu.inner().cooler_policy_table(i as u32, policy).is_ok(),
+                                }).map(|policy| policy.to_str()).collect(),
+                        }).collect::<Vec<_>>())
+                    }).collect::<Result<Vec<_>, Error>>()?;
+
+                    match oformat {
+                        OutputFormat::Human => for ((gpu, &index), coolers) in gpus.iter().zip(&indices).zip(&policies) {
+                            writeln!(out, "GPU {}: {}", index, gpu.inner().full_name()?)?;
+                            human::print_cooler_policies(out, coolers.iter())?;
+                        },
+                        OutputFormat::Json => {
+                            serde_json::to_writer_pretty(&mut *out, &gpu_json(serde_json::to_value(&policies)?, &indices, keyed))?
+                        },
+                    }
+                },
+                ("cooler", Some(matches)) if matches.is_present("characterize") => {
+                    let matches = matches.subcommand_matches("characterize").unwrap();
+                    let gpu = single_gpu(&gpus, &indices)?;
+                    let min = matches.value_of("min").map(u32::from_str).unwrap()?;
+                    let max = matches.value_of("max").map(u32::from_str).unwrap()?;
+                    let step = matches.value_of("step").map(u32::from_str).unwrap()?;
+                    let settle = matches.value_of("settle").map(parse_duration).unwrap()?;
+
+                    if step == 0 || min > max || max > 100 {
+                        return Err(Error::Str("expected 0 <= min <= max <= 100 and a non-zero step"))
+                    }
+
+                    let coolers = gpu.status()?.coolers.len();
+                    let _restore = CoolerRestore::new(gpu)?;
+                    let mut w = csv::Writer::from_writer(&mut *out);
+
+                    let mut level = min;
+                    loop {
+                        gpu.set_cooler_levels(iter::repeat_n(CoolerLevel {
+                            policy: CoolerPolicy::Manual,
+                            level: Percentage(level),
+                        }, coolers))?;
+
+                        // wait for two reads within 2% of each other
+                        let start = Instant::now();
+                        let mut rpm = gpu.status()?.tachometer;
+                        while start.elapsed() < settle {
+                            sleep(Duration::from_millis(500));
+                            let current = gpu.status()?.tachometer;
+                            let settled = match (rpm, current) {
+                                (Some(previous), Some(current)) => (current as i64 - previous as i64).abs() * 50 <= previous as i64,
+                                _ => true,
+                            };
+                            rpm = current;
+                            if settled {
+                                break
+                            }
+                        }
 
-                        gpu.inner().set_pstates([(pstate, clock, KilohertzDelta(delta))].iter().cloned())?
+                        info!("Cooler level {}: {} RPM", level, rpm.map(|r| r.to_string()).unwrap_or_else(|| "N/A".into()));
+                        w.serialize(CoolerSweepRow {
+                            level: Percentage(level),
+                            rpm,
+                        }).map_err(io::Error::from)?;
+                        w.flush()?;
+
+                        if level >= max {
+                            break
+                        }
+                        level = cmp::min(max, level + step);
                     }
                 },
                 ("cooler", Some(matches)) => {
+                    let mode = matches.value_of("policy").map(CoolerPolicy::from_str).invert()?;
+                    let level = matches.value_of("level").map(u32::from_str).invert()?;
+                    let index = matches.value_of("index").map(u32::from_str).invert()?;
+                    let all = matches.is_present("all");
+
+                    if mode.is_none() {
+                        return Err(Error::from("no cooler policy specified"))
+                    }
+
+                    // without a terminal read_line would hit EOF and restore right away
+                    let interactive = io::stdin().is_terminal();
+                    if matches.is_present("auto_restore") && !interactive {
+                        return Err(Error::Str("--auto-restore waits for enter and needs stdin to be a terminal, use --persist instead"))
+                    }
+                    let auto_restore = interactive && !matches.is_present("persist");
+                    let mut restore = Vec::new();
+
                     for gpu in &gpus {
-                        let mode = matches.value_of("policy").map(CoolerPolicy::from_str).unwrap()?;
-                        let level = matches.value_of("level").map(u32::from_str).unwrap()?;
+                        if auto_restore {
+                            restore.push(CoolerRestore::new(gpu)?);
+                        }
 
-                        gpu.set_cooler_levels(vec![CoolerLevel {
-                            policy: mode,
-                            level: Percentage(level),
-                        }].into_iter())?
+                        if let (Some(mode), Some(level)) = (mode, level) {
+                            let level = CoolerLevel {
+                                policy: mode,
+                                level: Percentage(level),
+                            };
+
+                            if let Some(name) = matches.value_of("name") {
+                                let names = gpu.status()?.coolers.iter().map(|(desc, _)| cooler_name(desc)).collect::<Vec<_>>();
+                                let index = names.iter().position(|n| n.eq_ignore_ascii_case(name))
+                                    .ok_or_else(|| Error::from(format!(
+                                        "unknown cooler \"{}\", expected one of: {}", name, names.join(", ")
+                                    )))?;
+                                gpu.inner().set_cooler_levels(Some(index as u32), iter::once(level))?
+                            } else if let Some(index) = index {
+                                gpu.inner().set_cooler_levels(Some(index), iter::once(level))?
+                            } else {
+                                let count = if all { gpu.status()?.coolers.len() } else { 1 };
+                                gpu.set_cooler_levels(iter::repeat_n(level, count))?
+                            }
+                        }
+                    }
+
+                    if !restore.is_empty() {
+                        match oformat {
+                            OutputFormat::Human => eprintln!("Press enter to restore the previous cooler policy (use --persist to keep it)"),
+                            _ => info!("Waiting for enter to restore the previous cooler policy"),
+                        }
+                        io::stdin().read_line(&mut String::new())?;
                     }
                 },
                 ("vfp", Some(matches)) => {
                     match matches.subcommand() {
-                        ("export", Some(matches)) => {
-                            let gpu = single_gpu(&gpus)?;
-                            let delimiter = if matches.is_present("tabs") { b'\t' } else { b',' };
+                        ("export", Some(matches)) | ("snapshot", Some(matches)) => {
                             let output = matches.value_of("output").unwrap();
+                            let delimiter = csv_delimiter(matches, output)?;
+                            let headers = !matches.is_present("no_header");
+                            let format = vfp_format(matches, output)?;
+                            let multiple = gpus.len() > 1;
+                            // comment lines would make JSON unreadable, several curves go out as one document instead
+                            let mut curves = Vec::new();
+
+                            for (gpu, &index) in gpus.iter().zip(&indices) {
+                                let status = gpu.status()?;
+                                let settings = gpu.settings()?;
 
-                            let status = gpu.status()?;
-                            let settings = gpu.settings()?;
+                                let (vfp, vfp_deltas) = match (status.vfp, settings.vfp) {
+                                    (Some(vfp), Some(vfp_deltas)) => (vfp, vfp_deltas),
+                                    _ if multiple => {
+                                        warn!("Skipping GPU {}: VFP not supported", index);
+                                        continue
+                                    },
+                                    _ => return Err(Status::NotSupported.into()),
+                                };
 
-                            let points = status.vfp.ok_or(Status::NotSupported)?.graphics
-                                .into_iter().zip(settings.vfp.ok_or(Status::NotSupported)?.graphics.into_iter())
-                                .map(|((i0, point), (i1, delta))| {
-                                    assert_eq!(i0, i1);
-                                    VfPoint::new(point, delta)
-                                });
+                                let points = vfp.graphics
+                                    .into_iter().zip(vfp_deltas.graphics)
+                                    .map(|((i0, point), (i1, delta))| {
+                                        assert_eq!(i0, i1);
+                                        VfPoint::new(point, delta)
+                                    });
 
-                            if is_std(output) {
-                                export_vfp(io::stdout(), points, delimiter)
-                            } else {
-                                export_vfp(fs::File::create(output)?, points, delimiter)
-                            }?
+                                match format {
+                                    VfpFormat::Json if is_std(output) && multiple => {
+                                        curves.push((index, points.collect::<Vec<_>>()));
+                                        Ok(())
+                                    },
+                                    _ if is_std(output) => {
+                                        if multiple {
+                                            writeln!(out, "# GPU {}: {}", index, gpu.inner().full_name()?)?;
+                                        }
+                                        export_vfp(&mut *out, points, format, delimiter, headers)
+                                    },
+                                    _ if multiple => export_vfp(fs::File::create(gpu_output_path(output, index))?, points, format, delimiter, headers),
+                                    _ => export_vfp(fs::File::create(output)?, points, format, delimiter, headers),
+                                }?
+                            }
+
+                            if !curves.is_empty() {
+                                let (curve_indices, curves): (Vec<_>, Vec<_>) = curves.into_iter().unzip();
+                                serde_json::to_writer_pretty(&mut *out, &gpu_json(serde_json::to_value(&curves)?, &curve_indices, keyed))?;
+                                writeln!(out)?;
+                            }
                         },
                         ("import", Some(matches)) => {
-                            for gpu in &gpus {
-                                let delimiter = if matches.is_present("tabs") { b'\t' } else { b',' };
-                                let input = matches.value_of("input").unwrap();
+                            let from_voltage = matches.value_of("from_voltage").map(u32::from_str).invert()?.map(Microvolts);
+                            let to_voltage = matches.value_of("to_voltage").map(u32::from_str).invert()?.map(Microvolts);
+                            let input = matches.value_of("input").unwrap();
+                            let delimiter = csv_delimiter(matches, input)?;
+                            let headers = !matches.is_present("no_header");
+                            let format = vfp_format(matches, input)?;
+
+                            // read once, stdin can't be read again for the next GPU
+                            let input = if is_std(input) {
+                                import_vfp(io::stdin(), format, delimiter, headers)
+                            } else {
+                                import_vfp(fs::File::open(input)?, format, delimiter, headers)
+                            }?;
+
+                            let mut previews = Vec::new();
+                            let mut reports = Vec::new();
+                            for (gpu, &index) in gpus.iter().zip(&indices) {
+                                let policy = policy.for_gpu(index);
 
                                 let status = gpu.status()?;
                                 let vfp = status.vfp.ok_or(Status::NotSupported)?.graphics;
 
-                                fn import<R: io::Read>(read: R, delimiter: u8) -> Result<Vec<VfPoint>, csv::Error> {
-                                    let mut csv = csv::ReaderBuilder::new().delimiter(delimiter).from_reader(read);
-                                    let de = csv.deserialize();
+                                let deltas = gpu.settings()?.vfp.ok_or(Status::NotSupported)?.graphics;
+                                let curve = vfp.iter()
+                                    .filter_map(|(&i, point)| deltas.get(&i).map(|&delta| (i, VfPoint::new(point.clone(), delta))))
+                                    .collect::<BTreeMap<_, _>>();
+
+                                let mut unmatched = Vec::new();
+                                let mut anchors = BTreeMap::new();
+                                for point in input.iter()
+                                    .filter(|p| from_voltage.is_none_or(|v| p.voltage >= v))
+                                    .filter(|p| to_voltage.is_none_or(|v| p.voltage <= v))
+                                {
+                                    match vfp.iter().find(|(_, v)| v.voltage == point.voltage) {
+                                        Some((&i, _)) => { anchors.insert(i, point.delta); },
+                                        None => unmatched.push(VfpImportEntry {
+                                            index: None,
+                                            voltage: point.voltage,
+                                            delta: point.delta,
+                                            previous: None,
+                                        }),
+                                    }
+                                }
+
+                                if matches.is_present("interpolate") {
+                                    let range = gpu.info()?.vfp_limits.get(&ClockDomain::Graphics)
+                                        .ok_or(Status::NotSupported)?.range;
+                                    anchors = curve::interpolate(&curve, &anchors, &range);
+                                }
 
-                                    de.collect()
+                                if matches.is_present("monotonic") {
+                                    enforce_monotonic(&curve, &mut anchors, matches.is_present("strict"))?;
                                 }
 
-                                let input = if is_std(input) {
-                                    import(io::stdin(), delimiter)
-                                } else {
-                                    import(fs::File::open(input)?, delimiter)
-                                }.map_err(io::Error::from)?;
+                                for delta in anchors.values_mut() {
+                                    *delta = policy.vfp_delta(*delta)?;
+                                }
+
+                                let entries = anchors.into_iter().map(|(i, delta)| VfpImportEntry {
+                                    index: Some(i),
+                                    voltage: vfp[&i].voltage,
+                                    delta,
+                                    previous: None,
+                                }).chain(unmatched).collect::<Vec<_>>();
+
+                                if matches.is_present("dry_run") {
+                                    previews.push(entries.into_iter().map(|entry| VfpImportEntry {
+                                        previous: entry.index.and_then(|i| deltas.get(&i).cloned()),
+                                        .. entry
+                                    }).collect::<Vec<_>>());
+                                    continue
+                                }
+
+                                for entry in entries.iter().filter(|e| e.index.is_none()) {
+                                    warn!("No curve point at {}, skipping", entry.voltage);
+                                }
+
+                                gpu.inner().set_vfp_table(
+                                    [0, 0, 0, 0],
+                                    entries.iter().filter_map(|e| e.index.map(|i| (i, e.delta.into()))),
+                                    ::std::iter::empty(),
+                                )?;
+
+                                let report = and_test(matches, gpu, index, gpus.len() > 1, oformat, || {
+                                    gpu.inner().set_vfp_table(
+                                        [0, 0, 0, 0],
+                                        entries.iter().filter_map(|e| e.index)
+                                            .filter_map(|i| deltas.get(&i).map(|&delta| (i, delta.into()))),
+                                        ::std::iter::empty(),
+                                    ).map_err(Error::from)
+                                })?;
+                                reports.extend(report.map(|report| (index, report)));
+                            }
+
+                            if reports.iter().any(|(_, report)| !report.passed) {
+                                exit_code = error::EXIT_FAILURE;
+                            }
+                            print_test_reports(out, oformat, reports, keyed, clock_unit)?;
+
+                            if matches.is_present("dry_run") {
+                                match oformat {
+                                    OutputFormat::Human => for entries in &previews {
+                                        human::print_vfp_import(out, entries.iter())?;
+                                    },
+                                    OutputFormat::Json => {
+                                        serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&previews, clock_unit)?, &indices, keyed))?
+                                    },
+                                }
+                            }
+                        },
+                        ("verify", Some(matches)) => {
+                            let input = matches.value_of("input").unwrap();
+                            let delimiter = csv_delimiter(matches, input)?;
+                            let headers = !matches.is_present("no_header");
+                            let format = vfp_format(matches, input)?;
+                            let tolerance = matches.value_of("tolerance").map(i32::from_str).unwrap()? * 1000;
+
+                            let input = if is_std(input) {
+                                import_vfp(io::stdin(), format, delimiter, headers)
+                            } else {
+                                import_vfp(fs::File::open(input)?, format, delimiter, headers)
+                            }?;
+
+                            let results = gpus.iter().map(|gpu| {
+                                let vfp = gpu.status()?.vfp.ok_or(Status::NotSupported)?.graphics;
+                                let deltas = gpu.settings()?.vfp.ok_or(Status::NotSupported)?.graphics;
+
+                                Ok(verify_vfp(&vfp, &deltas, &input, tolerance))
+                            }).collect::<Result<Vec<_>, Error>>()?;
+
+                            if results.iter().flat_map(|e| e.iter()).any(|e| !e.matches) {
+                                exit_code = error::EXIT_FAILURE;
+                            }
+
+                            match oformat {
+                                OutputFormat::Human => for entries in &results {
+                                    human::print_vfp_verify(out, entries.iter())?;
+                                },
+                                OutputFormat::Json => {
+                                    serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&results, clock_unit)?, &indices, keyed))?
+                                },
+                            }
+                        },
+                        ("restore", Some(matches)) => {
+                            let input = matches.value_of("input").unwrap();
+                            let format = vfp_format(matches, input)?;
+                            let tolerance = matches.value_of("tolerance").map(i32::from_str).unwrap()? * 1000;
+                            let multiple = gpus.len() > 1;
+
+                            let mut results = Vec::new();
+                            for (gpu, &index) in gpus.iter().zip(&indices) {
+                                let policy = policy.for_gpu(index);
+                                let path = if multiple { gpu_output_path(input, index) } else { input.to_owned() };
+                                let snapshot = import_vfp(fs::File::open(&path)?, format, csv_delimiter(matches, &path)?, true)?;
+
+                                let vfp = gpu.status()?.vfp.ok_or(Status::NotSupported)?.graphics;
+                                let deltas = snapshot.iter()
+                                    .filter_map(|point| vfp.iter()
+                                        .find(|(_, v)| v.voltage == point.voltage)
+                                        .map(|(&i, _)| (i, point.delta))
+                                    ).map(|(i, delta)| policy.vfp_delta(delta).map(|delta| (i, delta)))
+                                    .collect::<Result<Vec<_>, _>>()?;
 
                                 gpu.inner().set_vfp_table(
                                     [0, 0, 0, 0],
-                                    input.into_iter().filter_map(|point|
-                                        vfp.iter()
-                                            .find(|&(_, ref v)| v.voltage == point.voltage)
-                                            .map(|(&i, _)| (i, point.delta.into()))
-                                    ),
+                                    deltas.iter().map(|&(i, delta)| (i, delta.into())),
                                     ::std::iter::empty(),
                                 )?;
+
+                                // read back what the driver kept, it may silently reject or clamp points
+                                let applied = gpu.settings()?.vfp.ok_or(Status::NotSupported)?.graphics;
+                                results.push(verify_vfp(&vfp, &applied, &snapshot, tolerance));
+                            }
+
+                            if results.iter().flat_map(|e| e.iter()).any(|e| !e.matches) {
+                                exit_code = error::EXIT_FAILURE;
+                            }
+
+                            match oformat {
+                                OutputFormat::Human => for entries in &results {
+                                    human::print_vfp_verify(out, entries.iter())?;
+                                },
+                                OutputFormat::Json => {
+                                    serde_json::to_writer_pretty(&mut *out, &gpu_json(clock_json(&results, clock_unit)?, &indices, keyed))?
+                                },
                             }
                         },
                         ("lock", Some(matches)) => {
-                            for gpu in &gpus {
-                                let point = matches.value_of("point").map(u32::from_str).unwrap()?;
-                                let v = if matches.is_present("voltage") {
+                            let mode = matches.value_of("mode").map(ClockLockMode::from_str).invert()?;
+                            let min_voltage = matches.value_of("min_voltage").map(u32::from_str).invert()?.map(Microvolts);
+                            let max_voltage = matches.value_of("max_voltage").map(u32::from_str).invert()?.map(Microvolts);
+                            for (gpu, &index) in gpus.iter().zip(&indices) {
+                                let policy = policy.for_gpu(index);
+                                if mode == Some(ClockLockMode::None) {
+                                    gpu.reset_vfp_lock()?;
+                                    continue
+                                }
+
+                                let point = matches.value_of("point").map(u32::from_str).invert()?
+                                    .ok_or(Error::Str("a manual lock needs a POINT"))?;
+                                let v = if matches.is_present("nearest_voltage") {
+                                    let nearest = gpu.status()?.vfp.ok_or(Status::NotSupported)?.graphics.values()
+                                        .map(|p| p.voltage)
+                                        .min_by_key(|v| (v.0 as i64 - point as i64).abs())
+                                        .ok_or(Error::Str("empty voltage curve"))?;
+                                    if let OutputFormat::Human = oformat {
+                                        writeln!(out, "Locking to nearest curve voltage {}", nearest)?;
+                                    }
+                                    nearest
+                                } else if matches.is_present("voltage") {
                                     Microvolts(point)
                                 } else {
                                     gpu.status()?.vfp.ok_or(Status::NotSupported)?.graphics.get(&(point as usize))
@@ -729,21 +2619,35 @@ fn main_result() -> Result<i32, Error> {
                                         .voltage
                                 };
 
+                                let v = policy.lock_voltage(v, min_voltage, max_voltage)?;
                                 gpu.set_vfp_lock(v)?;
                             }
                         },
-                        ("unlock", Some(..)) => {
+                        ("locks", Some(..)) => {
+                            print_vfp_locks(out, oformat, &gpus, &indices, keyed)?;
+                        },
+                        ("unlock", Some(matches)) => {
+                            if matches.is_present("list") {
+                                print_vfp_locks(out, oformat, &gpus, &indices, keyed)?;
+                            }
+
                             for gpu in &gpus {
                                 gpu.reset_vfp_lock()?;
                             }
                         },
                         ("auto", Some(matches)) => {
-                            let gpu = single_gpu(&gpus)?;
+                            let gpu = single_gpu(&gpus, &indices)?;
+                            let policy = policy.for_gpu(indices[0]);
 
                             let end = matches.value_of("end").map(usize::from_str).invert()?;
                             let start = matches.value_of("start").map(usize::from_str).unwrap()?;
-                            let step = matches.value_of("step").map(i32::from_str).unwrap()?;
-                            let max = matches.value_of("max").map(u32::from_str).unwrap()?;
+                            let voltage_min = matches.value_of("voltage_min").map(u32::from_str).invert()?.map(Microvolts);
+                            let voltage_max = matches.value_of("voltage_max").map(u32::from_str).invert()?.map(Microvolts);
+                            let boost = gpu.info()?.boost_clocks.get(&ClockDomain::Graphics).cloned();
+                            let step = matches.value_of("step").map(|v| parse_frequency(v, boost)).unwrap()?;
+                            let max = matches.value_of("max").map(|v| parse_frequency(v, boost)).unwrap()?;
+                            let settle_tolerance = matches.value_of("settle_tolerance").map(i32::from_str).unwrap()?;
+                            let settle_max = matches.value_of("settle_max").map(u64::from_str).unwrap()?;
 
                             let status = gpu.status()?;
                             let vfp = status.vfp.ok_or(Status::NotSupported)?;
@@ -753,47 +2657,163 @@ fn main_result() -> Result<i32, Error> {
 
                             let options = auto::AutoDetectOptions {
                                 fan_override: matches.is_present("fan"),
-                                step: KilohertzDelta(step * 1000),
+                                step: KilohertzDelta(step.0 as i32),
                                 test: matches.value_of("test").map(|v| v.to_owned()),
+                                exit_codes: auto::ExitCodes {
+                                    pass: exit_codes(matches, "pass_codes")?.unwrap_or_else(|| vec![0]),
+                                    fail: exit_codes(matches, "fail_codes")?,
+                                    retry: exit_codes(matches, "retry_codes")?.unwrap_or_default(),
+                                    abort: exit_codes(matches, "abort_codes")?.unwrap_or_default(),
+                                },
+                                test_env: matches.values_of("test_env").into_iter().flatten().map(parse_env).collect::<Result<_, _>>()?,
+                                test_args: matches.value_of("test_args").map(split_args).invert()?.unwrap_or_default(),
                                 voltage_wait_delay: Duration::from_secs(2),
-                                max_frequency: Kilohertz(max * 1000),
+                                max_frequency: max,
+                                settle_tolerance: KilohertzDelta(settle_tolerance * 1000),
+                                settle_max: Duration::from_millis(settle_max),
+                                strategy: matches.value_of("strategy").map(AutoStrategy::from_str).unwrap()?,
+                                detect_reset: matches.value_of("detect_reset") == Some(POSSIBLE_BOOL_ON),
+                                policy: policy.clamping(),
+                            };
+
+                            // read the baseline if it exists, otherwise capture the curve as it is before tuning
+                            let baseline = match matches.value_of("baseline") {
+                                Some(path) => {
+                                    let format = match extension(path).as_ref().map(|e| &e[..]) {
+                                        Some("json") => VfpFormat::Json,
+                                        _ => VfpFormat::Csv,
+                                    };
+
+                                    let delimiter = csv_delimiter(matches, path)?;
+
+                                    if ::std::path::Path::new(path).exists() {
+                                        Some(import_vfp(fs::File::open(path)?, format, delimiter, true)?)
+                                    } else {
+                                        let curve = vfp.graphics.iter()
+                                            .filter_map(|(i, point)| vfp_delta.graphics.get(i).map(|&delta| VfPoint::new(point.clone(), delta)))
+                                            .collect::<Vec<_>>();
+                                        export_vfp(fs::File::create(path)?, curve.iter().cloned(), format, delimiter, true)?;
+                                        Some(curve)
+                                    }
+                                },
+                                None => None,
                             };
 
                             let mut auto = auto::AutoDetect::new(&gpu, options)?;
-                            let mut results: BTreeMap<usize, VfPoint> = Default::default();
+                            let mut results: BTreeMap<usize, AutoResultRow> = Default::default();
 
+                            let events = matches.is_present("events_json");
+                            let event = |event: serde_json::Value| if events {
+                                let _ = writeln!(io::stderr(), "{}", event);
+                            };
+
+                            let progress = match oformat {
+                                OutputFormat::Human => true,
+                                OutputFormat::Json => false,
+                            };
+                            let sweep_start = Instant::now();
+
+                            event(json!({ "event": "prepare" }));
                             auto.test_prepare()?;
 
-                            for (i, point, delta) in (start..end).rev()
+                            let points = (start..end).rev()
                                 .filter_map(|i| vfp.graphics.get(&i).map(|v| (i, v)))
+                                .filter(|&(_, v)| voltage_min.map(|min| v.voltage >= min).unwrap_or(true))
+                                .filter(|&(_, v)| voltage_max.map(|max| v.voltage <= max).unwrap_or(true))
                                 .map(|(i, v)| (i, v, vfp_delta.graphics.get(&i).unwrap()))
-                            {
+                                .collect::<Vec<_>>();
+
+                            for (n, &(i, point, delta)) in points.iter().enumerate() {
+                                if progress && n > 0 {
+                                    let elapsed = sweep_start.elapsed();
+                                    let remaining = elapsed / n as u32 * (points.len() - n) as u32;
+                                    let _ = writeln!(io::stderr(), "Point {}/{}, {} elapsed, about {} remaining",
+                                        n + 1, points.len(), format_duration(elapsed), format_duration(remaining)
+                                    );
+                                }
+
+                                event(json!({ "event": "point_start", "index": i, "voltage": point.voltage, "frequency": point.frequency }));
+
                                 match auto.test_point(i, point.voltage, point.frequency, *delta) {
-                                    Ok(Some((delta, frequency))) => {
-                                        results.insert(i, VfPoint {
+                                    Ok(Some(result)) => {
+                                        info!("found best point: {:#?}", result.frequency);
+                                        event(json!({
+                                            "event": "point_result", "index": i, "voltage": point.voltage,
+                                            "frequency": result.frequency, "delta": result.delta, "failed": result.failed,
+                                        }));
+
+                                        results.insert(i, AutoResultRow {
                                             voltage: point.voltage,
-                                            frequency: frequency,
-                                            delta: delta,
+                                            frequency: result.frequency,
+                                            delta: result.delta,
+                                            failed: result.failed,
+                                            gain: baseline.as_ref()
+                                                .and_then(|b| b.iter().find(|b| b.voltage == point.voltage))
+                                                .map(|b| result.frequency - b.frequency),
                                         });
-
-                                        info!("found best point: {:#?}", frequency);
                                     },
-                                    Ok(None) => (),
+                                    Ok(None) => event(json!({ "event": "point_failed", "index": i, "voltage": point.voltage })),
                                     Err(e) => {
+                                        event(json!({ "event": "point_failed", "index": i, "voltage": point.voltage, "error": e.to_string() }));
+                                        event(json!({ "event": "cleanup" }));
                                         let _ = auto.test_cleanup();
 
-                                        let _ = export_vfp(io::stdout(), results.into_iter().map(|(_, v)| v), b',');
+                                        let _ = export_auto(&mut *out, results.values());
 
                                         return Err(e)
                                     },
                                 }
                             }
 
+                            event(json!({ "event": "cleanup" }));
                             let res = auto.test_cleanup();
 
-                            let io_res = export_vfp(io::stdout(), results.into_iter().map(|(_, v)| v), b',');
+                            let io_res = export_auto(&mut *out, results.values());
+                            event(json!({ "event": "done", "points": results.len() }));
+                            if progress {
+                                let _ = writeln!(io::stderr(), "Tested {} points in {}", points.len(), format_duration(sweep_start.elapsed()));
+
+                                let gains = results.iter()
+                                    .map(|(i, row)| (row, row.delta - vfp_delta.graphics.get(i).cloned().unwrap_or_default()))
+                                    .collect::<Vec<_>>();
+                                let _ = writeln!(io::stderr(), "Stable offsets found for {} of {} points", gains.len(), points.len());
+                                if !gains.is_empty() {
+                                    let average = gains.iter().map(|&(_, gain)| gain.0 as i64).sum::<i64>() / gains.len() as i64;
+                                    let _ = writeln!(io::stderr(), "Average gain: {}", KilohertzDelta(average as i32));
+                                }
+                                if let Some(&(row, gain)) = gains.iter().max_by_key(|&&(_, gain)| gain) {
+                                    let _ = writeln!(io::stderr(), "Largest gain: {} at {} ({})", gain, row.voltage, row.frequency);
+                                }
+
+                                let baseline_gains = results.values().filter_map(|row| row.gain).collect::<Vec<_>>();
+                                if !baseline_gains.is_empty() {
+                                    let average = baseline_gains.iter().map(|gain| gain.0 as i64).sum::<i64>() / baseline_gains.len() as i64;
+                                    let _ = writeln!(io::stderr(), "Average gain vs baseline: {}", KilohertzDelta(average as i32));
+                                }
+                            }
 
                             let _ = res.and_then(|_| io_res.map_err(From::from))?;
+
+                            if matches.is_present("apply") {
+                                let curve = vfp.graphics.iter()
+                                    .filter_map(|(&i, point)| vfp_delta.graphics.get(&i).map(|&delta| (i, VfPoint::new(point.clone(), delta))))
+                                    .collect::<BTreeMap<_, _>>();
+                                let mut deltas = results.into_iter().map(|(i, point)| (i, point.delta)).collect();
+
+                                if matches.is_present("monotonic") {
+                                    enforce_monotonic(&curve, &mut deltas, matches.is_present("strict"))?;
+                                }
+
+                                for delta in deltas.values_mut() {
+                                    *delta = policy.vfp_delta(*delta)?;
+                                }
+
+                                gpu.inner().set_vfp_table(
+                                    [0, 0, 0, 0],
+                                    deltas.into_iter().map(|(i, delta)| (i, delta.into())),
+                                    ::std::iter::empty(),
+                                )?;
+                            }
                         },
                         _ => unreachable!("unknown command"),
                     }
@@ -805,8 +2825,572 @@ fn main_result() -> Result<i32, Error> {
                 _ => unreachable!("unknown command"),
             }
         },
+        ("doctor", Some(..)) => return doctor(matches, Ok(()), out),
+        ("apply-env", Some(..)) => {
+            let mut global = vec!["nvoclock".to_owned(), format!("--output-format={}", oformat.to_str())];
+            global.extend(gpu.into_iter().flatten().map(|g| format!("--gpu={}", g)));
+
+            let mut applied = false;
+            for &(var, command) in APPLY_ENV {
+                let value = match env::var(var) {
+                    Ok(value) => value,
+                    Err(env::VarError::NotPresent) => continue,
+                    Err(e) => return Err(Error::from(format!("{}: {}", var, e))),
+                };
+
+                let args = global.iter().map(|a| &a[..])
+                    .chain(command.iter().cloned())
+                    .chain(value.split_whitespace());
+                let env_matches = app().get_matches_from_safe(args)
+                    .map_err(|e| Error::from(format!("{}={}: {}", var, value, e.message)))?;
+
+                info!("Applying {}={}", var, value);
+                match run(&env_matches, out) {
+                    Ok(0) => applied = true,
+                    Ok(code) => return Ok(code),
+                    Err(e) => {
+                        error!("Failed to apply {}={}", var, value);
+                        return Err(e)
+                    },
+                }
+            }
+
+            if !applied {
+                warn!("No NVOCLOCK_* settings found in the environment");
+            }
+        },
+        ("batch", Some(matches)) => {
+            // global options apply to every line
+            let mut global = vec!["nvoclock".to_owned(), format!("--output-format={}", oformat.to_str())];
+            global.extend(gpu.into_iter().flatten().map(|g| format!("--gpu={}", g)));
+
+            return run_script(matches.value_of("input").unwrap(), &global, out)
+        },
+        ("guard", Some(matches)) => {
+            let interval = matches.value_of("interval").map(parse_duration).unwrap()?;
+            let hang_timeout = matches.value_of("hang_timeout").map(parse_duration).unwrap()?;
+            // resolve names now, the profile and the hang checks address GPUs by index
+            let indices = gpu_indices(&Gpu::enumerate()?, gpu)?;
+
+            let mut global = vec!["nvoclock".to_owned(), format!("--output-format={}", oformat.to_str())];
+            global.extend(indices.iter().map(|g| format!("--gpu={}", g)));
+
+            match run_script(matches.value_of("profile").unwrap(), &global, out)? {
+                0 => (),
+                code => return Ok(code),
+            }
+
+            info!("Profile applied, watching for hangs");
+            loop {
+                sleep(interval);
+
+                // status() never returns from a hung GPU, so ping from a thread that can be abandoned
+                let (send, recv) = mpsc::channel();
+                let ping = indices.clone();
+                thread::spawn(move || {
+                    let result = Gpu::enumerate().and_then(|gpus| ping.iter()
+                        .map(|&i| gpus.get(i).ok_or(Status::NvidiaDeviceNotFound).and_then(|gpu| gpu.status()))
+                        .collect::<Result<Vec<_>, _>>()
+                    );
+                    let _ = send.send(result.map(|_| ()));
+                });
+
+                let failure = match recv.recv_timeout(hang_timeout) {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(e)) => Error::from(e).to_string(),
+                    Err(..) => format!("no response after {:?}", hang_timeout),
+                };
+
+                error!("GPU stopped responding ({}), resetting overclock settings", failure);
+                let reset_matches = app().get_matches_from(global.iter().map(|a| &a[..]).chain(iter::once("reset")));
+                run(&reset_matches, out)?;
+                return Ok(error::EXIT_FAILURE)
+            }
+        },
         _ => unreachable!("unknown command"),
     }
 
     Ok(exit_code)
 }
+
+/// Splits a command line into arguments like a shell would, minus expansions.
+///
+/// Single quotes keep everything up to the closing quote, double quotes and bare
+/// words treat a backslash as escaping the next character.
+fn split_args(line: &str) -> Result<Vec<String>, Error> {
+    let mut args = Vec::new();
+    let mut arg = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(Error::Str("unterminated single quote")),
+                    }
+                }
+            },
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => arg.push(chars.next().ok_or(Error::Str("unterminated double quote"))?),
+                        Some(c) => arg.push(c),
+                        None => return Err(Error::Str("unterminated double quote")),
+                    }
+                }
+            },
+            '\\' => arg.get_or_insert_with(String::new).push(chars.next().ok_or(Error::Str("trailing backslash"))?),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+
+    Ok(args)
+}
+
+/// Runs each line of the file `input` as a command, prefixed with the `global` arguments.
+///
+/// Stops at the first line that fails. There's no rollback, the lines before it stay applied.
+fn run_script(input: &str, global: &[String], out: &mut dyn Write) -> Result<i32, Error> {
+    let mut script = String::new();
+    if is_std(input) {
+        io::stdin().read_to_string(&mut script)?;
+    } else {
+        fs::File::open(input)?.read_to_string(&mut script)?;
+    }
+
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+
+        let words = split_args(line).map_err(|e| Error::from(format!("{}:{}: {}", input, i + 1, e)))?;
+        let args = global.iter().chain(&words);
+        let line_matches = app().get_matches_from_safe(args)
+            .map_err(|e| Error::from(format!("{}:{}: {}", input, i + 1, e.message)))?;
+        match line_matches.subcommand_name() {
+            Some(command @ "batch") | Some(command @ "guard") =>
+                return Err(Error::from(format!("{}:{}: {} cannot be nested", input, i + 1, command))),
+            _ => (),
+        }
+
+        info!("Running {}:{}: {}", input, i + 1, line);
+        match run(&line_matches, out) {
+            Ok(0) => (),
+            Ok(code) => return Ok(code),
+            Err(e) => {
+                error!("Stopped at {}:{}: {}", input, i + 1, line);
+                return Err(e)
+            },
+        }
+    }
+
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use serde::Serialize;
+    use serde_json::{self, Value};
+    use nvapi::{Kilohertz, KilohertzDelta, Microvolts, Kibibytes, Percentage, Celsius, VfPoint, VfpPoint, PState, PStateLimit, ClockDomain, VoltageDomain, UtilizationDomain, Range,
+        GpuStatus, GpuSettings, VfpTable, VfpDeltas, MemoryInfo, PerfStatus, ClockLockEntry, ClockLockMode};
+    use nvapi::nvapi::PerfFlags;
+    use types::{ResetSettings, VfpFormat, OutputFormat, ClockUnit, SchemaKind, EffectiveClock, VfpImportEntry, VfpVerifyEntry, TestReport, HeadroomEntry, HeadroomConstraint};
+    use conv::ConvertEnum;
+    use schema::schema;
+    use super::{app, csv_delimiter, split_args, sensor_index, clock_json, gpu_json, parse_frequency, reset_settings, leftover_offsets, export_vfp, import_vfp, resolve_output_format, check_pstate_clock};
+
+    fn points() -> Vec<VfPoint> {
+        vec![
+            VfPoint { voltage: Microvolts(0), frequency: Kilohertz(0), delta: KilohertzDelta(0) },
+            VfPoint { voltage: Microvolts(800000), frequency: Kilohertz(1544500), delta: KilohertzDelta(-100000) },
+            VfPoint { voltage: Microvolts(1093750), frequency: Kilohertz(2012000), delta: KilohertzDelta(150000) },
+            VfPoint { voltage: Microvolts(u32::MAX), frequency: Kilohertz(u32::MAX), delta: KilohertzDelta(i32::MIN) },
+        ]
+    }
+
+    fn round_trip(format: VfpFormat, delimiter: u8, headers: bool) -> Vec<VfPoint> {
+        let mut buf = Vec::new();
+        export_vfp(&mut buf, points().into_iter(), format, delimiter, headers).unwrap();
+        import_vfp(&buf[..], format, delimiter, headers).unwrap()
+    }
+
+    #[test]
+    fn vfp_csv_round_trip() {
+        assert_eq!(round_trip(VfpFormat::Csv, b',', true), points());
+        assert_eq!(round_trip(VfpFormat::Csv, b'\t', true), points());
+        assert_eq!(round_trip(VfpFormat::Csv, b';', false), points());
+    }
+
+    #[test]
+    fn vfp_json_round_trip() {
+        assert_eq!(round_trip(VfpFormat::Json, b',', true), points());
+    }
+
+    #[test]
+    fn vfp_csv_header() {
+        let mut buf = Vec::new();
+        export_vfp(&mut buf, points().into_iter().take(1), VfpFormat::Csv, b',', true).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "voltage_uV,frequency_kHz,delta_kHz\n0,0,0\n");
+    }
+
+    #[test]
+    fn vfp_csv_legacy_header() {
+        let csv = "voltage,frequency,delta\n800000,1544500,-100000\n";
+        assert_eq!(import_vfp(csv.as_bytes(), VfpFormat::Csv, b',', true).unwrap(), vec![points()[1].clone()]);
+    }
+
+    #[test]
+    fn output_format_precedence() {
+        assert_eq!(resolve_output_format(None, None, None).unwrap(), OutputFormat::Human);
+        assert_eq!(resolve_output_format(None, None, Some("status.json")).unwrap(), OutputFormat::Json);
+        assert_eq!(resolve_output_format(None, Some("human"), Some("status.json")).unwrap(), OutputFormat::Human);
+        assert_eq!(resolve_output_format(None, Some("json"), None).unwrap(), OutputFormat::Json);
+        assert_eq!(resolve_output_format(Some("human"), Some("json"), Some("status.json")).unwrap(), OutputFormat::Human);
+        assert_eq!(resolve_output_format(Some("JSON"), Some("human"), None).unwrap(), OutputFormat::Json);
+        assert!(resolve_output_format(None, Some("xml"), None).is_err());
+    }
+
+    #[test]
+    fn pstate_clock_domains() {
+        let limit = |delta: Option<i32>| PStateLimit {
+            frequency_delta: delta.map(|d| Range { min: KilohertzDelta(-d), max: KilohertzDelta(d) }),
+            frequency: Range { min: Kilohertz(0), max: Kilohertz(2000000) },
+            voltage: Range { min: Microvolts(0), max: Microvolts(1200000) },
+            voltage_domain: VoltageDomain::Core,
+        };
+        let clocks = vec![
+            (ClockDomain::Graphics, limit(Some(1000000))),
+            (ClockDomain::Memory, limit(None)),
+        ].into_iter().collect::<BTreeMap<_, _>>();
+
+        assert!(check_pstate_clock(&clocks, PState::P0, ClockDomain::Graphics).is_ok());
+        assert!(check_pstate_clock(&clocks, PState::P0, ClockDomain::Memory).is_err());
+        assert!(check_pstate_clock(&clocks, PState::P0, ClockDomain::Video).is_err());
+        assert!(check_pstate_clock(&clocks, PState::P0, ClockDomain::Processor).is_err());
+    }
+
+    #[test]
+    fn frequency_sign() {
+        assert_eq!(parse_frequency("+50", None).unwrap(), Kilohertz(50000));
+        assert_eq!(parse_frequency("50", None).unwrap(), Kilohertz(50000));
+        assert!(parse_frequency("-50", None).is_err());
+    }
+
+    #[test]
+    fn app_builds() {
+        // clap only validates the definitions (unique shorts and such) when parsing in debug builds
+        app().get_matches_from(vec!["nvoclock", "list"]);
+    }
+
+    fn delimiter(args: &[&str], path: &str) -> u8 {
+        let matches = app().get_matches_from(args.iter().cloned());
+        let (_, matches) = matches.subcommand();
+        let (_, matches) = matches.unwrap().subcommand();
+        let (_, matches) = matches.unwrap().subcommand();
+        csv_delimiter(matches.unwrap(), path).unwrap()
+    }
+
+    #[test]
+    fn thermal_sensor_index() {
+        let names = vec!["gpu".to_owned(), "memory".to_owned(), "gpu".to_owned()];
+        assert_eq!(sensor_index(&names, "memory").unwrap(), 1);
+        assert_eq!(sensor_index(&names, "2").unwrap(), 2);
+        assert!(sensor_index(&names, "gpu").is_err());
+        assert!(sensor_index(&names, "3").is_err());
+        assert!(sensor_index(&names, "vrm").is_err());
+    }
+
+    #[test]
+    fn split_quoted_args() {
+        assert_eq!(split_args("set  pstate -p P0 -c graphics +=50").unwrap(), vec!["set", "pstate", "-p", "P0", "-c", "graphics", "+=50"]);
+        assert_eq!(split_args(r#"set -g "GeForce GTX 1080" cooler"#).unwrap(), vec!["set", "-g", "GeForce GTX 1080", "cooler"]);
+        assert_eq!(split_args(r#"--and-test 'stress --gpu "all"' a\ b"#).unwrap(), vec!["--and-test", r#"stress --gpu "all""#, "a b"]);
+        assert_eq!(split_args(r#"'' "a\"b""#).unwrap(), vec!["", "a\"b"]);
+        assert!(split_args("\"open").is_err());
+        assert!(split_args("'open").is_err());
+    }
+
+    #[test]
+    fn tsv_delimiter() {
+        assert_eq!(delimiter(&["nvoclock", "set", "vfp", "export", "curve.tsv"], "curve.tsv"), b'\t');
+        assert_eq!(delimiter(&["nvoclock", "set", "vfp", "export", "curve.csv"], "curve.csv"), b',');
+        assert_eq!(delimiter(&["nvoclock", "set", "vfp", "export", "-d", ";", "curve.tsv"], "curve.tsv"), b';');
+    }
+
+    fn reset(args: &[&str]) -> (Vec<ResetSettings>, bool) {
+        let matches = app().get_matches_from(args.iter().cloned());
+        let (_, matches) = matches.subcommand();
+        reset_settings(matches.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn reset_selected() {
+        assert_eq!(reset(&["nvoclock", "reset", "power"]), (vec![ResetSettings::PowerLimits], true));
+        assert_eq!(reset(&["nvoclock", "reset", "--pstate", "P0"]), (vec![ResetSettings::PStateDeltas], true));
+    }
+
+    #[test]
+    fn reset_all() {
+        let (settings, explicit) = reset(&["nvoclock", "reset"]);
+        assert_eq!(settings.len(), ResetSettings::possible_values_typed().len());
+        assert!(!explicit);
+    }
+
+    #[test]
+    fn reset_leftover_offsets() {
+        let mut set = GpuSettings {
+            voltage_boost: None,
+            sensor_limits: Vec::new(),
+            power_limits: Vec::new(),
+            coolers: Vec::new(),
+            vfp: Some(VfpDeltas {
+                graphics: vec![(0, KilohertzDelta(0)), (1, KilohertzDelta(0))].into_iter().collect(),
+                memory: BTreeMap::new(),
+            }),
+            pstate_deltas: vec![
+                (PState::P0, vec![(ClockDomain::Graphics, KilohertzDelta(0)), (ClockDomain::Memory, KilohertzDelta(0))].into_iter().collect()),
+                (PState::P2, vec![(ClockDomain::Graphics, KilohertzDelta(0))].into_iter().collect()),
+            ].into_iter().collect(),
+            overvolt: Vec::new(),
+            vfp_locks: BTreeMap::new(),
+        };
+        assert_eq!(leftover_offsets(&set, ResetSettings::VfpDeltas, None), 0);
+        assert_eq!(leftover_offsets(&set, ResetSettings::PStateDeltas, None), 0);
+
+        set.vfp.as_mut().unwrap().graphics.insert(1, KilohertzDelta(-15000));
+        set.pstate_deltas.get_mut(&PState::P2).unwrap().insert(ClockDomain::Graphics, KilohertzDelta(50000));
+        assert_eq!(leftover_offsets(&set, ResetSettings::VfpDeltas, None), 1);
+        assert_eq!(leftover_offsets(&set, ResetSettings::PStateDeltas, None), 1);
+        assert_eq!(leftover_offsets(&set, ResetSettings::PStateDeltas, Some(&[PState::P0])), 0);
+        assert_eq!(leftover_offsets(&set, ResetSettings::PowerLimits, None), 0);
+    }
+
+    #[test]
+    fn reset_order() {
+        let (settings, _) = reset(&["nvoclock", "reset", "vfp", "power", "pstate", "lock", "vfp"]);
+        assert_eq!(settings, vec![
+            ResetSettings::VfpLock, ResetSettings::PStateDeltas, ResetSettings::VfpDeltas, ResetSettings::PowerLimits,
+        ]);
+
+        let (settings, _) = reset(&["nvoclock", "reset"]);
+        let position = |setting| settings.iter().position(|&s| s == setting).unwrap();
+        assert!(position(ResetSettings::VfpLock) < position(ResetSettings::PStateDeltas));
+        assert!(position(ResetSettings::PStateDeltas) < position(ResetSettings::VfpDeltas));
+    }
+
+    fn status() -> GpuStatus {
+        GpuStatus {
+            pstate: PState::P0,
+            clocks: vec![(ClockDomain::Graphics, Kilohertz(1544500)), (ClockDomain::Memory, Kilohertz(4006000))].into_iter().collect(),
+            memory: MemoryInfo {
+                dedicated: Kibibytes(8388608),
+                dedicated_available: Kibibytes(8388608),
+                system: Kibibytes(0),
+                shared: Kibibytes(4194304),
+                dedicated_available_current: Kibibytes(7340032),
+                dedicated_evictions_size: Kibibytes(0),
+                dedicated_evictions: 0,
+            },
+            voltage: Some(Microvolts(800000)),
+            voltage_domains: None,
+            voltage_step: None,
+            voltage_table: None,
+            tachometer: Some(1500),
+            utilization: vec![(UtilizationDomain::Graphics, Percentage(30))].into_iter().collect(),
+            power: vec![Percentage(80)],
+            sensors: Vec::new(),
+            coolers: Vec::new(),
+            perf: PerfStatus {
+                unknown: 0,
+                limits: PerfFlags::empty(),
+            },
+            vfp: Some(VfpTable {
+                graphics: vec![(0, VfpPoint { frequency: Kilohertz(1544500), voltage: Microvolts(800000) })].into_iter().collect(),
+                memory: BTreeMap::new(),
+            }),
+            vfp_locks: vec![(0, Microvolts(800000))].into_iter().collect(),
+        }
+    }
+
+    fn settings() -> GpuSettings {
+        GpuSettings {
+            voltage_boost: Some(Percentage(0)),
+            sensor_limits: Vec::new(),
+            power_limits: vec![Percentage(100)],
+            coolers: Vec::new(),
+            vfp: Some(VfpDeltas {
+                graphics: vec![(0, KilohertzDelta(-15000))].into_iter().collect(),
+                memory: BTreeMap::new(),
+            }),
+            pstate_deltas: vec![
+                (PState::P0, vec![(ClockDomain::Graphics, KilohertzDelta(100500))].into_iter().collect()),
+            ].into_iter().collect(),
+            overvolt: Vec::new(),
+            vfp_locks: vec![(0, ClockLockEntry { mode: ClockLockMode::Manual, voltage: Microvolts(800000) })].into_iter().collect(),
+        }
+    }
+
+    /// Just enough JSON Schema validation for what `schema` emits
+    fn validate(schema: &Value, value: &Value, root: &Value) -> Result<(), String> {
+        if let Some(name) = schema.get("$ref").and_then(Value::as_str) {
+            return validate(&root["definitions"][name.trim_start_matches("#/definitions/")], value, root)
+        }
+
+        if let Some(schemas) = schema.get("anyOf").and_then(Value::as_array) {
+            return if schemas.iter().any(|s| validate(s, value, root).is_ok()) {
+                Ok(())
+            } else {
+                Err(format!("{} matches none of {}", value, schema))
+            }
+        }
+
+        for s in schema.get("allOf").and_then(Value::as_array).into_iter().flatten() {
+            validate(s, value, root)?;
+        }
+
+        let typed = match schema.get("type").and_then(Value::as_str) {
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("number") => value.is_number(),
+            Some("string") => value.is_string(),
+            Some("boolean") => value.is_boolean(),
+            Some("array") => value.is_array(),
+            Some("object") => value.is_object(),
+            Some("null") => value.is_null(),
+            Some(ty) => panic!("unexpected schema type {}", ty),
+            None => true,
+        };
+        if !typed {
+            return Err(format!("{} is not {}", value, schema["type"]))
+        }
+
+        if let Some(fields) = value.as_object() {
+            for name in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+                if !fields.contains_key(name) {
+                    return Err(format!("missing {}", name))
+                }
+            }
+
+            for (name, value) in fields {
+                let field = schema.get("properties").and_then(|p| p.get(name))
+                    .or_else(|| schema.get("additionalProperties"));
+                match field {
+                    Some(field) => validate(field, value, root).map_err(|e| format!("{}: {}", name, e))?,
+                    None if schema.get("properties").is_some() => return Err(format!("unexpected {}", name)),
+                    None => (),
+                }
+            }
+        }
+
+        if let Some(values) = value.as_array() {
+            for (i, value) in values.iter().enumerate() {
+                let item = match schema.get("items") {
+                    Some(Value::Array(items)) => items.get(i),
+                    item => item,
+                };
+                if let Some(item) = item {
+                    validate(item, value, root).map_err(|e| format!("[{}]: {}", i, e))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_schema<T: Serialize>(kind: SchemaKind, value: &T, omit: &[&str]) {
+        for &unit in &[ClockUnit::Khz, ClockUnit::Mhz] {
+            let mut value = clock_json(value, unit).unwrap();
+            for field in omit {
+                value[0].as_object_mut().unwrap().remove(*field);
+            }
+            for &keyed in &[false, true] {
+                let output = gpu_json(value.clone(), &[1], keyed);
+                let schema = schema(kind, keyed, unit);
+                if let Err(e) = validate(&schema, &output, &schema) {
+                    panic!("{:?} output ({:?}, keyed: {}) doesn't match its schema: {}", kind, unit, keyed, e)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn schema_status() {
+        let status = vec![status()];
+        check_schema(SchemaKind::Status, &status, &[]);
+        check_schema(SchemaKind::Status, &status, super::SUMMARY_OMITS);
+    }
+
+    #[test]
+    fn schema_settings() {
+        check_schema(SchemaKind::Settings, &vec![settings()], &[]);
+    }
+
+    #[test]
+    fn clock_json_mhz() {
+        let status = clock_json(&status(), ClockUnit::Mhz).unwrap();
+        assert_eq!(status["clocks"]["Graphics"], json!(1544.5));
+        assert_eq!(status["vfp"]["graphics"]["0"]["frequency"], json!(1544.5));
+        assert_eq!(status["vfp"]["graphics"]["0"]["voltage"], json!(800000));
+        assert_eq!(status["voltage"], json!(800000));
+        assert_eq!(status["vfp_locks"]["0"], json!(800000));
+
+        let set = clock_json(&settings(), ClockUnit::Mhz).unwrap();
+        assert_eq!(set["pstate_deltas"]["P0"]["Graphics"], json!(100.5));
+        assert_eq!(set["vfp"]["graphics"]["0"], json!(-15.0));
+        assert_eq!(set["vfp_locks"]["0"]["voltage"], json!(800000));
+
+        let effective = clock_json(&EffectiveClock {
+            pstate: PState::P0,
+            clock: ClockDomain::Graphics,
+            base: Kilohertz(1544500),
+            delta: KilohertzDelta(-15000),
+            effective: Kilohertz(1529500),
+        }, ClockUnit::Mhz).unwrap();
+        assert_eq!(effective, json!({ "pstate": "P0", "clock": "Graphics", "base": 1544.5, "delta": -15.0, "effective": 1529.5 }));
+
+        let import = clock_json(&VfpImportEntry {
+            index: Some(3),
+            voltage: Microvolts(800000),
+            delta: KilohertzDelta(15000),
+            previous: None,
+        }, ClockUnit::Mhz).unwrap();
+        assert_eq!(import, json!({ "index": 3, "voltage": 800000, "delta": 15.0, "previous": null }));
+
+        let verify = clock_json(&VfpVerifyEntry {
+            index: Some(3),
+            voltage: Microvolts(800000),
+            expected: KilohertzDelta(15000),
+            actual: Some(KilohertzDelta(14500)),
+            matches: false,
+        }, ClockUnit::Mhz).unwrap();
+        assert_eq!(verify, json!({ "index": 3, "voltage": 800000, "expected": 15.0, "actual": 14.5, "matches": false }));
+
+        let report = clock_json(&TestReport {
+            passed: true,
+            peak_temperature: Some(Celsius(70)),
+            peak_clock: Some(Kilohertz(1911000)),
+            reverted: false,
+        }, ClockUnit::Mhz).unwrap();
+        assert_eq!(report, json!({ "passed": true, "peak_temperature": 70, "peak_clock": 1911.0, "reverted": false }));
+
+        let headroom = clock_json(&vec![
+            HeadroomEntry::new(HeadroomConstraint::Clock, 1911000, 2000000),
+            HeadroomEntry::new(HeadroomConstraint::Voltage, 1000000, 1093000),
+        ], ClockUnit::Mhz).unwrap();
+        assert_eq!(headroom[0]["current"], json!(1911.0));
+        assert_eq!(headroom[0]["limit"], json!(2000.0));
+        assert_eq!(headroom[1]["current"], json!(1000000));
+
+        let khz = serde_json::to_value(settings()).unwrap();
+        assert_eq!(clock_json(&settings(), ClockUnit::Khz).unwrap(), khz);
+    }
+}
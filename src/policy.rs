@@ -0,0 +1,141 @@
+use std::{cmp, env};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use nvapi::{Percentage, Celsius, KilohertzDelta, Microvolts};
+use serde_json;
+use error::Error;
+
+/// Environment variable naming the policy file
+pub const POLICY_ENV: &str = "NVOCLOCK_POLICY";
+
+/// Administrator-configured ceilings applied to every write.
+///
+/// Loaded from the JSON file named by `NVOCLOCK_POLICY`, e.g.
+/// `{ "max_voltage_boost": 50, "max_pstate_offset": 150, "refuse": true }`.
+/// Offsets are in MHz. Values over a ceiling are clamped, or rejected if `refuse` is set.
+/// Voltage locks outside `min_lock_voltage`..`max_lock_voltage` (uV) are always rejected.
+///
+/// `gpus` overrides any of these for a single GPU, keyed by its index:
+/// `{ "max_pstate_offset": 150, "gpus": { "1": { "max_pstate_offset": 100 } } }`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    pub max_voltage_boost: Option<u32>,
+    pub max_pstate_offset: Option<i32>,
+    pub max_vfp_offset: Option<i32>,
+    pub max_power_limit: Option<u32>,
+    pub max_thermal_limit: Option<i32>,
+    pub min_lock_voltage: Option<u32>,
+    pub max_lock_voltage: Option<u32>,
+    pub refuse: bool,
+    pub gpus: BTreeMap<String, PolicyOverride>,
+}
+
+/// Per-GPU section of a `Policy`, unset fields fall back to the global ones
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PolicyOverride {
+    pub max_voltage_boost: Option<u32>,
+    pub max_pstate_offset: Option<i32>,
+    pub max_vfp_offset: Option<i32>,
+    pub max_power_limit: Option<u32>,
+    pub max_thermal_limit: Option<i32>,
+    pub min_lock_voltage: Option<u32>,
+    pub max_lock_voltage: Option<u32>,
+    pub refuse: Option<bool>,
+}
+
+impl Policy {
+    pub fn load() -> Result<Self, Error> {
+        match env::var_os(POLICY_ENV) {
+            Some(path) => {
+                let file = File::open(&path)?;
+                Ok(serde_json::from_reader(file)?)
+            },
+            None => Ok(Default::default()),
+        }
+    }
+
+    /// The same caps, clamping rather than refusing, for values nvoclock picks itself instead of the user
+    pub fn clamping(&self) -> Policy {
+        Policy {
+            refuse: false,
+            .. self.clone()
+        }
+    }
+
+    /// The policy for the GPU at enumeration `index`, with its section merged over the global values
+    pub fn for_gpu(&self, index: usize) -> Policy {
+        let mut policy = Policy {
+            gpus: Default::default(),
+            .. self.clone()
+        };
+
+        if let Some(o) = self.gpus.get(&index.to_string()) {
+            policy.max_voltage_boost = o.max_voltage_boost.or(policy.max_voltage_boost);
+            policy.max_pstate_offset = o.max_pstate_offset.or(policy.max_pstate_offset);
+            policy.max_vfp_offset = o.max_vfp_offset.or(policy.max_vfp_offset);
+            policy.max_power_limit = o.max_power_limit.or(policy.max_power_limit);
+            policy.max_thermal_limit = o.max_thermal_limit.or(policy.max_thermal_limit);
+            policy.min_lock_voltage = o.min_lock_voltage.or(policy.min_lock_voltage);
+            policy.max_lock_voltage = o.max_lock_voltage.or(policy.max_lock_voltage);
+            policy.refuse = o.refuse.unwrap_or(policy.refuse);
+        }
+
+        policy
+    }
+
+    fn limit<T: PartialOrd + Display>(&self, name: &str, value: T, max: Option<T>) -> Result<T, Error> {
+        match max {
+            Some(max) if value > max => if self.refuse {
+                Err(Error::from(format!("{} {} exceeds the policy maximum of {}", name, value, max)))
+            } else {
+                warn!("{} {} clamped to the policy maximum of {}", name, value, max);
+                Ok(max)
+            },
+            _ => Ok(value),
+        }
+    }
+
+    pub fn voltage_boost(&self, value: Percentage) -> Result<Percentage, Error> {
+        self.limit("voltage boost", value, self.max_voltage_boost.map(Percentage))
+    }
+
+    pub fn power_limit(&self, value: Percentage) -> Result<Percentage, Error> {
+        self.limit("power limit", value, self.max_power_limit.map(Percentage))
+    }
+
+    pub fn thermal_limit(&self, value: Celsius) -> Result<Celsius, Error> {
+        self.limit("thermal limit", value, self.max_thermal_limit.map(Celsius))
+    }
+
+    pub fn pstate_delta(&self, value: KilohertzDelta) -> Result<KilohertzDelta, Error> {
+        self.limit("pstate offset", value, self.max_pstate_offset.map(|v| KilohertzDelta(v * 1000)))
+    }
+
+    /// Checks a voltage lock against the window given on the command line, narrowed to the policy's.
+    ///
+    /// The command line can only tighten the policy window, never widen it.
+    pub fn lock_voltage(&self, value: Microvolts, min: Option<Microvolts>, max: Option<Microvolts>) -> Result<Microvolts, Error> {
+        let min = match (min, self.min_lock_voltage.map(Microvolts)) {
+            (Some(min), Some(policy)) => Some(cmp::max(min, policy)),
+            (min, policy) => min.or(policy),
+        };
+        let max = match (max, self.max_lock_voltage.map(Microvolts)) {
+            (Some(max), Some(policy)) => Some(cmp::min(max, policy)),
+            (max, policy) => max.or(policy),
+        };
+
+        if min.is_some_and(|min| value < min) || max.is_some_and(|max| value > max) {
+            let bound = |v: Option<Microvolts>| v.map(|v| v.to_string()).unwrap_or_else(|| "any".into());
+            Err(Error::from(format!("lock voltage {} is outside the allowed window of {} to {}", value, bound(min), bound(max))))
+        } else {
+            Ok(value)
+        }
+    }
+
+    pub fn vfp_delta(&self, value: KilohertzDelta) -> Result<KilohertzDelta, Error> {
+        self.limit("curve offset", value, self.max_vfp_offset.map(|v| KilohertzDelta(v * 1000)))
+    }
+}
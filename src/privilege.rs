@@ -0,0 +1,71 @@
+use error::Error;
+
+/// Whether the process is elevated, `None` when that can't be determined on this platform.
+#[cfg(windows)]
+pub fn elevated() -> Option<bool> {
+    use std::os::raw::c_void;
+    use std::{mem, ptr};
+
+    type Handle = *mut c_void;
+    const TOKEN_QUERY: u32 = 0x0008;
+    // TOKEN_INFORMATION_CLASS::TokenElevation
+    const TOKEN_ELEVATION: u32 = 20;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentProcess() -> Handle;
+        fn CloseHandle(handle: Handle) -> i32;
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn OpenProcessToken(process: Handle, access: u32, token: *mut Handle) -> i32;
+        fn GetTokenInformation(token: Handle, class: u32, info: *mut c_void, len: u32, ret_len: *mut u32) -> i32;
+    }
+
+    unsafe {
+        let mut token = ptr::null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return None
+        }
+
+        // TOKEN_ELEVATION is a single DWORD, non-zero when the token is elevated
+        let mut elevation = 0u32;
+        let mut len = 0u32;
+        let res = GetTokenInformation(token, TOKEN_ELEVATION, &mut elevation as *mut u32 as *mut c_void, mem::size_of::<u32>() as u32, &mut len);
+        CloseHandle(token);
+
+        if res == 0 {
+            None
+        } else {
+            Some(elevation != 0)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn elevated() -> Option<bool> {
+    use std::fs;
+
+    // the second Uid field is the effective uid
+    fs::read_to_string("/proc/self/status").ok().and_then(|status| status.lines()
+        .find(|line| line.starts_with("Uid:"))
+        .and_then(|line| line.split_whitespace().nth(2))
+        .map(|uid| uid == "0")
+    )
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn elevated() -> Option<bool> {
+    None
+}
+
+/// Fails up front for commands that change settings, rather than on NVAPI's first refused write.
+///
+/// Unknown elevation is let through, the driver still has the final say.
+pub fn require() -> Result<(), Error> {
+    match elevated() {
+        Some(false) => Err(Error::Unprivileged),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,263 @@
+use serde_json::{Map, Value};
+use types::{SchemaKind, ClockUnit};
+
+const SCHEMA_DRAFT: &str = "http://json-schema.org/draft-07/schema#";
+
+fn unsigned() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/definitions/{}", name) })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn map(values: Value) -> Value {
+    json!({ "type": "object", "additionalProperties": values })
+}
+
+fn nullable(value: Value) -> Value {
+    json!({ "anyOf": [value, { "type": "null" }] })
+}
+
+fn tuple(a: Value, b: Value) -> Value {
+    json!({ "type": "array", "items": [a, b], "minItems": 2, "maxItems": 2 })
+}
+
+fn object(properties: Vec<(&str, Value)>) -> Value {
+    let required = properties.iter().map(|&(name, _)| Value::from(name)).collect::<Vec<_>>();
+    let properties: Map<String, Value> = properties.into_iter().map(|(name, v)| (name.to_owned(), v)).collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// `object`, with `fields` left out of the required properties
+fn optional(mut object: Value, fields: &[&str]) -> Value {
+    if let Some(&mut Value::Array(ref mut required)) = object.get_mut("required") {
+        required.retain(|name| !fields.iter().any(|f| name == f));
+    }
+
+    object
+}
+
+fn range(unit: &str) -> Value {
+    object(vec![
+        ("min", reference(unit)),
+        ("max", reference(unit)),
+    ])
+}
+
+fn definitions(unit: ClockUnit) -> Value {
+    let (clock, clock_unit) = match unit {
+        ClockUnit::Khz => ("integer", "kHz"),
+        ClockUnit::Mhz => ("number", "MHz"),
+    };
+
+    json!({
+        "Celsius": { "type": "integer", "description": "Degrees Celsius" },
+        "Kilohertz": { "type": clock, "minimum": 0, "description": format!("Frequency in {}", clock_unit) },
+        "KilohertzDelta": { "type": clock, "description": format!("Frequency offset in {}", clock_unit) },
+        "Microvolts": { "type": "integer", "minimum": 0, "description": "Voltage in uV" },
+        "MicrovoltsDelta": { "type": "integer", "description": "Voltage offset in uV" },
+        "Kibibytes": { "type": "integer", "minimum": 0, "description": "Size in KiB" },
+        "Percentage": { "type": "integer", "minimum": 0, "description": "Percentage" },
+        "Enum": { "type": "string", "description": "NVAPI enum variant name" },
+        "Flags": object(vec![("bits", unsigned())]),
+        "PerfFlags": {
+            "allOf": [reference("Flags")],
+            "description": "Performance limiters: 1 power, 2 temperature, 4 voltage, 16 no load",
+        },
+        "ClockFrequencies": map(reference("Kilohertz")),
+        "MemoryInfo": object(vec![
+            ("dedicated", reference("Kibibytes")),
+            ("dedicated_available", reference("Kibibytes")),
+            ("system", reference("Kibibytes")),
+            ("shared", reference("Kibibytes")),
+            ("dedicated_available_current", reference("Kibibytes")),
+            ("dedicated_evictions_size", reference("Kibibytes")),
+            ("dedicated_evictions", unsigned()),
+        ]),
+        "SensorDesc": object(vec![
+            ("controller", reference("Enum")),
+            ("target", reference("Enum")),
+            ("range", range("Celsius")),
+        ]),
+        "CoolerDesc": object(vec![
+            ("kind", reference("Enum")),
+            ("controller", reference("Enum")),
+            ("range", range("Percentage")),
+            ("default_policy", reference("Enum")),
+            ("target", reference("Enum")),
+            ("control", reference("Enum")),
+        ]),
+        "CoolerStatus": object(vec![
+            ("range", range("Percentage")),
+            ("level", reference("Percentage")),
+            ("policy", reference("Enum")),
+            ("active", json!({ "type": "boolean" })),
+        ]),
+        "VoltageStatus": object(vec![
+            ("flags", unsigned()),
+            ("unknown0", unsigned()),
+            ("voltage", reference("Microvolts")),
+            ("count", unsigned()),
+            ("unknown1", array(unsigned())),
+        ]),
+        "VfpTable": object(vec![
+            ("graphics", map(object(vec![
+                ("frequency", reference("Kilohertz")),
+                ("voltage", reference("Microvolts")),
+            ]))),
+            ("memory", map(object(vec![
+                ("frequency", reference("Kilohertz")),
+                ("voltage", reference("Microvolts")),
+            ]))),
+        ]),
+        "VfpDeltas": object(vec![
+            ("graphics", map(reference("KilohertzDelta"))),
+            ("memory", map(reference("KilohertzDelta"))),
+        ]),
+    })
+}
+
+fn info() -> Value {
+    object(vec![
+        ("name", string()),
+        ("codename", string()),
+        ("bios_version", string()),
+        ("driver_model", object(vec![("value", unsigned())])),
+        ("vendor", reference("Enum")),
+        ("pci", object(vec![
+            ("device_id", unsigned()),
+            ("subsystem_id", unsigned()),
+            ("revision_id", unsigned()),
+            ("ext_device_id", unsigned()),
+        ])),
+        ("memory", reference("MemoryInfo")),
+        ("system_type", reference("Enum")),
+        ("ram_type", reference("Enum")),
+        ("ram_maker", reference("Enum")),
+        ("ram_bus_width", unsigned()),
+        ("ram_bank_count", unsigned()),
+        ("ram_partition_count", unsigned()),
+        ("foundry", reference("Enum")),
+        ("core_count", unsigned()),
+        ("shader_pipe_count", unsigned()),
+        ("shader_sub_pipe_count", unsigned()),
+        ("base_clocks", reference("ClockFrequencies")),
+        ("boost_clocks", reference("ClockFrequencies")),
+        ("sensors", array(reference("SensorDesc"))),
+        ("coolers", array(reference("CoolerDesc"))),
+        ("perf", object(vec![
+            ("max_unknown", unsigned()),
+            ("limits", reference("PerfFlags")),
+        ])),
+        ("sensor_limits", array(object(vec![
+            ("range", range("Celsius")),
+            ("default", reference("Celsius")),
+            ("flags", unsigned()),
+        ]))),
+        ("power_limits", array(object(vec![
+            ("range", range("Percentage")),
+            ("default", reference("Percentage")),
+        ]))),
+        ("pstate_limits", map(map(object(vec![
+            ("frequency_delta", nullable(range("KilohertzDelta"))),
+            ("frequency", range("Kilohertz")),
+            ("voltage", range("Microvolts")),
+            ("voltage_domain", reference("Enum")),
+        ])))),
+        ("overvolt_limits", array(object(vec![
+            ("domain", reference("Enum")),
+            ("voltage", reference("Microvolts")),
+            ("range", nullable(range("MicrovoltsDelta"))),
+        ]))),
+        ("vfp_limits", map(object(vec![
+            ("range", range("KilohertzDelta")),
+            ("temperature", reference("Celsius")),
+        ]))),
+        ("vfp_locks", array(unsigned())),
+    ])
+}
+
+fn status() -> Value {
+    // `status --summarize` leaves these out
+    optional(object(vec![
+        ("pstate", reference("Enum")),
+        ("clocks", reference("ClockFrequencies")),
+        ("memory", reference("MemoryInfo")),
+        ("voltage", nullable(reference("Microvolts"))),
+        ("voltage_domains", nullable(reference("VoltageStatus"))),
+        ("voltage_step", nullable(reference("VoltageStatus"))),
+        ("voltage_table", nullable(object(vec![
+            ("flags", unsigned()),
+            ("entries", array(object(vec![
+                ("unknown", unsigned()),
+                ("voltage", reference("Microvolts")),
+            ]))),
+        ]))),
+        ("tachometer", nullable(unsigned())),
+        ("utilization", map(reference("Percentage"))),
+        ("power", array(reference("Percentage"))),
+        ("sensors", array(tuple(reference("SensorDesc"), reference("Celsius")))),
+        ("coolers", array(tuple(reference("CoolerDesc"), reference("CoolerStatus")))),
+        ("perf", object(vec![
+            ("unknown", unsigned()),
+            ("limits", reference("PerfFlags")),
+        ])),
+        ("vfp", nullable(reference("VfpTable"))),
+        ("vfp_locks", map(reference("Microvolts"))),
+    ]), ::SUMMARY_OMITS)
+}
+
+fn settings() -> Value {
+    object(vec![
+        ("voltage_boost", nullable(reference("Percentage"))),
+        ("sensor_limits", array(reference("Celsius"))),
+        ("power_limits", array(reference("Percentage"))),
+        ("coolers", array(tuple(reference("CoolerDesc"), reference("CoolerStatus")))),
+        ("vfp", nullable(reference("VfpDeltas"))),
+        ("pstate_deltas", map(map(reference("KilohertzDelta")))),
+        ("overvolt", array(reference("MicrovoltsDelta"))),
+        ("vfp_locks", map(object(vec![
+            ("mode", reference("Enum")),
+            ("voltage", reference("Microvolts")),
+        ]))),
+    ])
+}
+
+/// JSON Schema describing the `-O json` output of the matching command, with
+/// `--json-keyed` and `--clock-unit` applied.
+///
+/// The nvapi types aren't local so the schema is maintained by hand and must be
+/// kept in sync with their serde representation, the tests check it against
+/// serialized values.
+pub fn schema(kind: SchemaKind, keyed: bool, unit: ClockUnit) -> Value {
+    let (title, item) = match kind {
+        SchemaKind::Info => ("nvoclock info", info()),
+        SchemaKind::Status => ("nvoclock status", status()),
+        SchemaKind::Settings => ("nvoclock get", settings()),
+    };
+
+    let mut schema = if keyed {
+        map(item)
+    } else {
+        array(item)
+    };
+    schema["$schema"] = SCHEMA_DRAFT.into();
+    schema["title"] = title.into();
+    schema["definitions"] = definitions(unit);
+
+    schema
+}
@@ -0,0 +1,125 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use nvapi::{Status, Gpu};
+use serde_json;
+use types::{GpuDescriptor, ClockUnit};
+use clocks::clock_json;
+use {Error, gpu_indices, gpu_json};
+
+/// How long a client gets to send its request or take the reply, connections are handled one at a time
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// JSON options given on the command line, applied to every response
+struct Options {
+    indices: Vec<usize>,
+    keyed: bool,
+    clock_unit: ClockUnit,
+}
+
+/// Serves read-only JSON over HTTP until the listener fails.
+///
+/// `/gpus`, `/gpus/{i}/info` and `/gpus/{i}/status`, one request per connection. Only
+/// the GPUs selected with `-g` are served, `--json-keyed` and `--clock-unit` apply as
+/// they do to the other commands.
+pub fn serve(addr: &str, gpu: Option<::clap::Values>, keyed: bool, clock_unit: ClockUnit) -> Result<(), Error> {
+    let gpus = Gpu::enumerate()?;
+    let options = Options {
+        indices: gpu_indices(&gpus, gpu)?,
+        keyed,
+        clock_unit,
+    };
+    let listener = TcpListener::bind(addr)?;
+    info!("Listening on {}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Failed to accept connection: {}", e);
+                continue
+            },
+        };
+
+        if let Err(e) = handle(&gpus, &options, stream) {
+            warn!("Failed to handle request: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle(gpus: &[Gpu], options: &Options, stream: TcpStream) -> io::Result<()> {
+    // a client that stalls would otherwise hold up every other one
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    // headers are ignored, but must be read before replying
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    debug!("{} {}", method, path);
+
+    let (code, body) = if method != "GET" {
+        (405, json!({ "error": "only GET is supported" }))
+    } else {
+        match route(gpus, options, path) {
+            Ok(Some(body)) => (200, body),
+            Ok(None) => (404, json!({ "error": "not found" })),
+            Err(e) => (500, json!({ "error": e.to_string() })),
+        }
+    };
+
+    let body = serde_json::to_string(&body).map_err(io::Error::from)?;
+    let reason = match code {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+
+    let mut stream = reader.into_inner();
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code, reason, body.len(), body
+    )?;
+    stream.flush()
+}
+
+fn route(gpus: &[Gpu], options: &Options, path: &str) -> Result<Option<serde_json::Value>, Error> {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+    match &segments[..] {
+        ["gpus"] => {
+            let gpus = options.indices.iter().map(|&i| Ok::<_, Status>(GpuDescriptor {
+                name: gpus[i].inner().full_name()?,
+                gpu_id: None,
+                pci: None,
+                memory: None,
+                bios_version: None,
+            })).collect::<Result<Vec<_>, _>>()?;
+            Ok(Some(gpu_json(serde_json::to_value(&gpus)?, &options.indices, options.keyed)))
+        },
+        ["gpus", index, kind] => {
+            let gpu = match index.parse::<usize>().ok().filter(|i| options.indices.contains(i)) {
+                Some(i) => &gpus[i],
+                None => return Ok(None),
+            };
+
+            let value = match *kind {
+                "info" => clock_json(&gpu.info()?, options.clock_unit)?,
+                "status" => clock_json(&gpu.status()?, options.clock_unit)?,
+                _ => return Ok(None),
+            };
+            Ok(Some(value))
+        },
+        _ => Ok(None),
+    }
+}
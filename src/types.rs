@@ -1,17 +1,328 @@
+use std::str::FromStr;
 use clap::ArgMatches;
+use serde::ser::{Serialize, Serializer, SerializeStruct};
+use nvapi::{GpuInfo, GpuStatus, GpuSettings, PState, ClockDomain, ClockLockMode, Celsius, Percentage, Microvolts, Kilohertz, KilohertzDelta, Kibibytes, PciIdentifiers, VfPoint};
+use error::Error;
 
+/// Everything `dump` captures about a single GPU
+#[derive(Debug, Clone, Serialize)]
+pub struct GpuDump {
+    pub info: GpuInfo,
+    pub status: GpuStatus,
+    pub settings: GpuSettings,
+}
+
+/// Entry of `list`, the optional fields are only filled in with `--verbose`.
 #[derive(Debug, Clone, Serialize)]
 pub struct GpuDescriptor {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci: Option<PciIdentifiers>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Kibibytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bios_version: Option<String>,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone, Serialize)]
+pub struct DriverDescriptor {
+    pub version: u32,
+    pub branch: String,
+    pub interface: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingDescriptor {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// CSV representation of a curve point, with units in the column names.
+///
+/// The bare field names written by older versions are still accepted on import.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VfpRow {
+    #[serde(rename = "voltage_uV", alias = "voltage")]
+    pub voltage: Microvolts,
+    #[serde(rename = "frequency_kHz", alias = "frequency")]
+    pub frequency: Kilohertz,
+    #[serde(rename = "delta_kHz", alias = "delta")]
+    pub delta: KilohertzDelta,
+}
+
+impl From<VfPoint> for VfpRow {
+    fn from(point: VfPoint) -> Self {
+        VfpRow {
+            voltage: point.voltage,
+            frequency: point.frequency,
+            delta: point.delta,
+        }
+    }
+}
+
+impl From<VfpRow> for VfPoint {
+    fn from(row: VfpRow) -> Self {
+        VfPoint {
+            voltage: row.voltage,
+            frequency: row.frequency,
+            delta: row.delta,
+        }
+    }
+}
+
+/// CSV row written by `vfp auto`, a curve point plus the lowest frequency that failed testing
+/// and, with `--baseline`, the gain over the baseline curve at the same voltage.
+///
+/// The extra columns are ignored when the file is imported.
+#[derive(Debug, Clone, Serialize)]
+pub struct AutoResultRow {
+    #[serde(rename = "voltage_uV")]
+    pub voltage: Microvolts,
+    #[serde(rename = "frequency_kHz")]
+    pub frequency: Kilohertz,
+    #[serde(rename = "delta_kHz")]
+    pub delta: KilohertzDelta,
+    #[serde(rename = "failed_kHz")]
+    pub failed: Option<Kilohertz>,
+    #[serde(rename = "gain_kHz")]
+    pub gain: Option<KilohertzDelta>,
+}
+
+/// CSV row written by `--sample-to-csv`, one GPU status sample taken while a test runs
+#[derive(Debug, Clone, Serialize)]
+pub struct TestSample {
+    #[serde(rename = "elapsed_ms")]
+    pub elapsed: u64,
+    #[serde(rename = "temperature_C")]
+    pub temperature: Option<Celsius>,
+    #[serde(rename = "clock_kHz")]
+    pub clock: Option<Kilohertz>,
+    #[serde(rename = "power_percent")]
+    pub power: Option<Percentage>,
+    #[serde(rename = "voltage_uV")]
+    pub voltage: Option<Microvolts>,
+}
+
+/// Policies accepted by one cooler, named as on the command line
+#[derive(Debug, Clone, Serialize)]
+pub struct CoolerPolicies {
+    pub cooler: String,
+    pub policies: Vec<&'static str>,
+}
+
+/// CSV row written by `set cooler characterize`, the fan speed a cooler level settled at.
+#[derive(Debug, Clone, Serialize)]
+pub struct CoolerSweepRow {
+    #[serde(rename = "level_percent")]
+    pub level: Percentage,
+    pub rpm: Option<u32>,
+}
+
+/// A row of an imported curve, resolved against the live curve.
+///
+/// `index` is `None` when no curve point matches the row's voltage.
+#[derive(Debug, Clone, Serialize)]
+pub struct VfpImportEntry {
+    pub index: Option<usize>,
+    pub voltage: Microvolts,
+    pub delta: KilohertzDelta,
+    pub previous: Option<KilohertzDelta>,
+}
+
+/// An active curve lock, see `vfp locks`.
+///
+/// `point` is `None` when the locked voltage isn't on the curve.
+#[derive(Debug, Clone, Serialize)]
+pub struct VfpLockEntry {
+    pub id: usize,
+    pub mode: ClockLockMode,
+    pub voltage: Microvolts,
+    pub point: Option<usize>,
+}
+
+/// A curve point read from a file compared against the offset currently applied.
+#[derive(Debug, Clone, Serialize)]
+pub struct VfpVerifyEntry {
+    pub index: Option<usize>,
+    pub voltage: Microvolts,
+    pub expected: KilohertzDelta,
+    pub actual: Option<KilohertzDelta>,
+    pub matches: bool,
+}
+
+/// A limit the GPU can run into, see `headroom`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum HeadroomConstraint {
+    Power,
+    Thermal,
+    Clock,
+    Voltage,
+}
+
+/// How close a GPU currently is to one of its limits.
+///
+/// `current` and `limit` are in the constraint's own unit: %, C, kHz or uV.
+#[derive(Debug, Clone)]
+pub struct HeadroomEntry {
+    pub constraint: HeadroomConstraint,
+    pub current: i64,
+    pub limit: i64,
+    pub usage: f64,
+}
+
+impl HeadroomEntry {
+    pub fn new(constraint: HeadroomConstraint, current: i64, limit: i64) -> Self {
+        HeadroomEntry {
+            constraint,
+            current,
+            limit,
+            usage: if limit > 0 { current as f64 * 100.0 / limit as f64 } else { 0.0 },
+        }
+    }
+}
+
+/// Clock constraints go out as `Kilohertz` so they follow `--clock-unit`
+impl Serialize for HeadroomEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("HeadroomEntry", 4)?;
+        s.serialize_field("constraint", &self.constraint)?;
+        match self.constraint {
+            HeadroomConstraint::Clock => {
+                s.serialize_field("current", &Kilohertz(self.current as u32))?;
+                s.serialize_field("limit", &Kilohertz(self.limit as u32))?;
+            },
+            _ => {
+                s.serialize_field("current", &self.current)?;
+                s.serialize_field("limit", &self.limit)?;
+            },
+        }
+        s.serialize_field("usage", &self.usage)?;
+        s.end()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeadroomReport {
+    pub name: String,
+    /// Ordered from the most to the least constrained
+    pub constraints: Vec<HeadroomEntry>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum ResetOutcome {
+    Reset,
+    /// Not supported by the GPU, only skipped when resetting everything
+    Unsupported,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResetEntry {
+    pub setting: &'static str,
+    pub outcome: ResetOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What `reset` did to a single GPU
+#[derive(Debug, Clone, Serialize)]
+pub struct ResetSummary {
+    pub name: String,
+    pub settings: Vec<ResetEntry>,
+}
+
+/// What `reset --dry-run` would change for one setting
+#[derive(Debug, Clone, Serialize)]
+pub struct ResetPreview {
+    pub setting: &'static str,
+    pub current: String,
+    pub reset_to: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResetPlan {
+    pub name: String,
+    pub settings: Vec<ResetPreview>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum DoctorResult {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// A single line of the `doctor` checklist
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub result: DoctorResult,
+    pub detail: String,
+}
+
+/// A pstate clock with its offset applied, see `get --effective`
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectiveClock {
+    pub pstate: PState,
+    pub clock: ClockDomain,
+    pub base: Kilohertz,
+    pub delta: KilohertzDelta,
+    pub effective: Kilohertz,
+}
+
+/// Outcome of `--and-test`, with the hottest sensor and fastest graphics clock seen while it ran
+#[derive(Debug, Clone, Serialize)]
+pub struct TestReport {
+    pub passed: bool,
+    pub peak_temperature: Option<Celsius>,
+    pub peak_clock: Option<Kilohertz>,
+    pub reverted: bool,
+}
+
+/// Temperatures at which `status` flags a sensor, `!` for warning and `!!` for critical.
+///
+/// When unset they default to 10C under and at the sensor's thermal limit.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TempThresholds {
+    pub warn: Option<Celsius>,
+    pub critical: Option<Celsius>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum OutputFormat {
     Human,
     Json,
 }
 
+/// Unit for clocks in JSON output, human output is always MHz
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClockUnit {
+    Khz,
+    Mhz,
+}
+
 #[derive(Debug, Copy, Clone)]
+pub enum VfpFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AutoStrategy {
+    Down,
+    Binary,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum SchemaKind {
+    Info,
+    Status,
+    Settings,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ResetSettings {
     VoltageBoost,
     SensorLimits,
@@ -23,6 +334,58 @@ pub enum ResetSettings {
     Overvolt,
 }
 
+impl ResetSettings {
+    /// Position in which `reset` applies the setting.
+    ///
+    /// Locks are released before offsets, and pstate offsets, which shift the whole curve,
+    /// are cleared before the per-point curve offsets so the final curve write starts from stock.
+    pub fn order(&self) -> u8 {
+        match *self {
+            ResetSettings::VfpLock => 0,
+            ResetSettings::PStateDeltas => 1,
+            ResetSettings::VfpDeltas => 2,
+            ResetSettings::Overvolt => 3,
+            ResetSettings::VoltageBoost => 4,
+            ResetSettings::PowerLimits => 5,
+            ResetSettings::SensorLimits => 6,
+            ResetSettings::CoolerLevels => 7,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum SettingField {
+    VoltageBoost,
+    ThermalLimit,
+    PowerLimit,
+    CoolerLevel,
+    Overvolt,
+    VfpLock,
+}
+
+/// A clock offset that either replaces the current one or is added to it (`+=25`/`-=25`).
+///
+/// Absolute offsets take an optional sign, so `+25` and `25` are the same.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeltaValue {
+    Absolute(i32),
+    Relative(i32),
+}
+
+impl FromStr for DeltaValue {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(delta) = s.strip_prefix("+=") {
+            Ok(DeltaValue::Relative(i32::from_str(delta)?))
+        } else if let Some(delta) = s.strip_prefix("-=") {
+            Ok(DeltaValue::Relative(-i32::from_str(delta)?))
+        } else {
+            Ok(DeltaValue::Absolute(i32::from_str(s)?))
+        }
+    }
+}
+
 pub const POSSIBLE_BOOL_OFF: &'static str = "off";
 pub const POSSIBLE_BOOL_ON: &'static str = "on";
 pub const POSSIBLE_BOOL: &'static [&'static str] = &[POSSIBLE_BOOL_OFF, POSSIBLE_BOOL_ON];
@@ -46,3 +409,23 @@ pub fn parse_bool_match(matches: &ArgMatches, arg: &'static str) -> bool {
         _ => unreachable!(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use super::DeltaValue;
+
+    #[test]
+    fn delta_sign() {
+        assert_eq!(DeltaValue::from_str("+50").unwrap(), DeltaValue::Absolute(50));
+        assert_eq!(DeltaValue::from_str("50").unwrap(), DeltaValue::Absolute(50));
+        assert_eq!(DeltaValue::from_str("-50").unwrap(), DeltaValue::Absolute(-50));
+    }
+
+    #[test]
+    fn delta_relative() {
+        assert_eq!(DeltaValue::from_str("+=50").unwrap(), DeltaValue::Relative(50));
+        assert_eq!(DeltaValue::from_str("+=+50").unwrap(), DeltaValue::Relative(50));
+        assert_eq!(DeltaValue::from_str("-=50").unwrap(), DeltaValue::Relative(-50));
+    }
+}